@@ -0,0 +1,193 @@
+use std::time::{Duration, Instant};
+
+use code::queue::{Consumer, Producer, Queue, QueueType};
+use code::PriorityConsumer;
+use core_affinity::CoreId;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const N_MESSAGES: usize = 4096;
+
+#[derive(Copy, Clone, Debug)]
+struct Msg<const N_BYTES: usize> {
+    data: [u8; N_BYTES],
+}
+
+impl<const N_BYTES: usize> Default for Msg<N_BYTES> {
+    fn default() -> Self {
+        Self { data: [0; N_BYTES] }
+    }
+}
+
+fn produce<const N_BYTES: usize>(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("produce_{N_BYTES}"));
+    group.throughput(Throughput::Elements(N_MESSAGES as u64));
+    let msgs = vec![Msg::<N_BYTES>::default(); N_MESSAGES];
+    for typ in [QueueType::SPMC, QueueType::MPMC] {
+        let q = Queue::new(N_MESSAGES, typ).unwrap();
+        let mut p = Producer::from(q);
+        group.bench_function(BenchmarkId::new(format!("{typ:?}"), 1), |b| {
+            b.iter(|| {
+                for m in &msgs {
+                    p.produce(m);
+                }
+            })
+        });
+        for batch in [8, 32, 128] {
+            group.bench_function(BenchmarkId::new(format!("{typ:?}"), batch), |b| {
+                b.iter(|| {
+                    for chunk in msgs.chunks(batch) {
+                        p.produce_batch(chunk);
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn consume<const N_BYTES: usize>(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("consume_{N_BYTES}"));
+    group.throughput(Throughput::Elements(N_MESSAGES as u64));
+    let msgs = vec![Msg::<N_BYTES>::default(); N_MESSAGES];
+    let q = Queue::new(N_MESSAGES, QueueType::SPMC).unwrap();
+    let mut p = Producer::from(q);
+    group.bench_function(BenchmarkId::from_parameter(1), |b| {
+        b.iter_batched(
+            || {
+                let c = Consumer::from(q);
+                p.produce_batch(&msgs);
+                c
+            },
+            |mut c| {
+                let mut m = Msg::<N_BYTES>::default();
+                while c.try_consume(&mut m).is_ok() {}
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+    for batch in [8, 32, 128] {
+        group.bench_function(BenchmarkId::from_parameter(batch), |b| {
+            b.iter_batched(
+                || {
+                    let c = Consumer::from(q);
+                    p.produce_batch(&msgs);
+                    c
+                },
+                |mut c| {
+                    let mut out = vec![Msg::<N_BYTES>::default(); batch];
+                    while c.consume_batch(&mut out).is_ok() {}
+                },
+                criterion::BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// every producer pushes N_MESSAGES per iteration, returns the time of the slowest one
+fn produce_from(q: &'static Queue<Msg<8>>, n_producers: usize, iters: u64) -> Duration {
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (0..n_producers)
+            .map(|i| {
+                s.spawn(move || {
+                    core_affinity::set_for_current(CoreId { id: 2 * i + 1 });
+                    let mut p = Producer::from(q);
+                    let m = Msg::<8>::default();
+                    let curt = Instant::now();
+                    for _ in 0..iters {
+                        for _ in 0..N_MESSAGES {
+                            p.produce(&m);
+                        }
+                    }
+                    curt.elapsed()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).max().unwrap()
+    })
+}
+
+fn contended_produce(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contended_produce");
+    let mpmc = Queue::new(N_MESSAGES, QueueType::MPMC).unwrap();
+    let sharded = Queue::new_sharded(N_MESSAGES, 8).unwrap();
+    for n_producers in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements((n_producers * N_MESSAGES) as u64));
+        group.bench_with_input(BenchmarkId::new("MPMC", n_producers), &n_producers, |b, &n| {
+            b.iter_custom(|iters| produce_from(mpmc, n, iters))
+        });
+        group.bench_with_input(BenchmarkId::new("Sharded", n_producers), &n_producers, |b, &n| {
+            b.iter_custom(|iters| produce_from(sharded, n, iters))
+        });
+    }
+    group.finish();
+}
+
+// Time from publishing a control message to reading it, with `backlog` bulk messages in front of it
+// either in the same queue or in a lower priority lane
+fn priority_latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("priority_latency");
+    let bulk_msg = Msg::<8>::default();
+    let control_msg = Msg::<8> { data: [1; 8] };
+    for backlog in [0, 64, 1024] {
+        let q = Queue::new(N_MESSAGES, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let mut cons = Consumer::from(q);
+        group.bench_with_input(BenchmarkId::new("single_queue", backlog), &backlog, |b, &n| {
+            b.iter_custom(|iters| {
+                let mut tot = Duration::ZERO;
+                let mut m = Msg::<8>::default();
+                for _ in 0..iters {
+                    for _ in 0..n {
+                        p.produce(&bulk_msg);
+                    }
+                    p.produce(&control_msg);
+                    let curt = Instant::now();
+                    while cons.try_consume(&mut m).is_ok() && m.data[0] == 0 {}
+                    tot += curt.elapsed();
+                }
+                tot
+            })
+        });
+
+        let bulk = Queue::new(N_MESSAGES, QueueType::SPMC).unwrap();
+        let control = Queue::new(N_MESSAGES, QueueType::SPMC).unwrap();
+        let mut pb = Producer::from(bulk);
+        let mut pc = Producer::from(control);
+        let mut cons = PriorityConsumer::default()
+            .with_lane(bulk, 0)
+            .with_lane(control, 1);
+        group.bench_with_input(BenchmarkId::new("priority_lanes", backlog), &backlog, |b, &n| {
+            b.iter_custom(|iters| {
+                let mut tot = Duration::ZERO;
+                let mut m = Msg::<8>::default();
+                for _ in 0..iters {
+                    for _ in 0..n {
+                        pb.produce(&bulk_msg);
+                    }
+                    pc.produce(&control_msg);
+                    let curt = Instant::now();
+                    while cons.try_consume(&mut m).is_ok() && m.data[0] == 0 {}
+                    tot += curt.elapsed();
+                    // drain the backlog outside of the measurement
+                    while cons.try_consume(&mut m).is_ok() {}
+                }
+                tot
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    queue,
+    produce::<8>,
+    produce::<32>,
+    produce::<128>,
+    consume::<8>,
+    consume::<32>,
+    consume::<128>,
+    contended_produce,
+    priority_latency
+);
+criterion_main!(queue);
@@ -5,7 +5,11 @@ use std::{
 };
 
 use core_affinity::CoreId;
-use criterion::{criterion_group, criterion_main, Bencher, BenchmarkId, Criterion, SamplingMode};
+use criterion::{
+    criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    Bencher, BenchmarkId, Criterion, SamplingMode, Throughput,
+};
 use rand::Rng;
 
 #[derive(Copy, Clone, Debug)]
@@ -19,9 +23,88 @@ impl<const N_BYTES: usize> Default for Msg<N_BYTES> {
     }
 }
 
-fn write_bench<const N_BYTES: usize>(b: &mut Bencher, n_contenders: usize) {
+trait Lock<T>: Send + Sync {
+    fn write(&self, val: &T);
+    fn read(&self, out: &mut T);
+    // single read attempt, to count retries
+    fn try_read(&self, out: &mut T) -> bool {
+        self.read(out);
+        true
+    }
+}
+
+impl<T: Copy + Send + Sync> Lock<T> for code::Seqlock<T> {
+    fn write(&self, val: &T) {
+        code::Seqlock::write(self, val)
+    }
+    fn read(&self, out: &mut T) {
+        code::Seqlock::read(self, out)
+    }
+    fn try_read(&self, out: &mut T) -> bool {
+        code::Seqlock::try_read(self, out)
+    }
+}
+
+impl<T: Copy + Send + Sync> Lock<T> for code::DoubleSeqlock<T> {
+    fn write(&self, val: &T) {
+        code::DoubleSeqlock::write(self, val)
+    }
+    fn read(&self, out: &mut T) {
+        code::DoubleSeqlock::read(self, out)
+    }
+    fn try_read(&self, out: &mut T) -> bool {
+        code::DoubleSeqlock::try_read(self, out)
+    }
+}
+
+impl<T: Copy + Send + Sync> Lock<T> for code::RcuCell<T> {
+    fn write(&self, val: &T) {
+        code::RcuCell::write(self, val)
+    }
+    fn read(&self, out: &mut T) {
+        code::RcuCell::read(self, out)
+    }
+}
+
+impl<T: Copy + Send + Sync> Lock<T> for code::SmallSeqlock<T> {
+    fn write(&self, val: &T) {
+        code::SmallSeqlock::write(self, val)
+    }
+    fn read(&self, out: &mut T) {
+        code::SmallSeqlock::read(self, out)
+    }
+}
+
+// lets the benches be generic over the lock while the message size is picked per size
+trait LockKind {
+    const NAME: &'static str;
+    type Lock<T: Copy + Default + Send + Sync>: Lock<T> + Default;
+
+    fn bench_id(size: usize) -> BenchmarkId {
+        BenchmarkId::new(Self::NAME, size)
+    }
+}
+
+struct SeqlockKind;
+impl LockKind for SeqlockKind {
+    const NAME: &'static str = "seqlock";
+    type Lock<T: Copy + Default + Send + Sync> = code::Seqlock<T>;
+
+    // the ids from before there were other locks, so saved baselines still compare
+    fn bench_id(size: usize) -> BenchmarkId {
+        BenchmarkId::from_parameter(size)
+    }
+}
+
+struct DoubleKind;
+impl LockKind for DoubleKind {
+    const NAME: &'static str = "double";
+    type Lock<T: Copy + Default + Send + Sync> = code::DoubleSeqlock<T>;
+}
+
+fn write_bench<K: LockKind, const N_BYTES: usize>(b: &mut Bencher, n_contenders: usize) {
     std::thread::scope(|s| {
-        let lock = Arc::new(code::Seqlock::default());
+        let lock = Arc::new(K::Lock::<Msg<N_BYTES>>::default());
         for i in 0..n_contenders {
             let lock2 = lock.clone();
             s.spawn(move || {
@@ -55,47 +138,52 @@ fn write_bench<const N_BYTES: usize>(b: &mut Bencher, n_contenders: usize) {
     });
 }
 
+fn write_sizes<K: LockKind>(group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>, n_readers: usize) {
+    for p in 4..=12 {
+        let size = 2usize.pow(p);
+        group.throughput(criterion::Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            K::bench_id(size),
+            &size,
+            |b, &size| match size {
+                16 => {
+                    write_bench::<K, 2>(b, n_readers);
+                }
+                32 => {
+                    write_bench::<K, 4>(b, n_readers);
+                }
+                64 => {
+                    write_bench::<K, 8>(b, n_readers);
+                }
+                128 => {
+                    write_bench::<K, 16>(b, n_readers);
+                }
+                256 => {
+                    write_bench::<K, 32>(b, n_readers);
+                }
+                512 => {
+                    write_bench::<K, 64>(b, n_readers);
+                }
+                1024 => {
+                    write_bench::<K, 128>(b, n_readers);
+                }
+                2048 => {
+                    write_bench::<K, 256>(b, n_readers);
+                }
+                4096 => {
+                    write_bench::<K, 512>(b, n_readers);
+                }
+                _ => {}
+            },
+        );
+    }
+}
+
 fn write(c: &mut Criterion) {
     for n_readers in 0..8 {
         let mut group = c.benchmark_group(format!("write_{}_readers", n_readers));
-        for p in 4..=12 {
-            let size = 2usize.pow(p);
-            group.throughput(criterion::Throughput::Bytes(size as u64));
-            group.bench_with_input(
-                BenchmarkId::from_parameter(size),
-                &size,
-                |b, &size| match size {
-                    16 => {
-                        write_bench::<2>(b, n_readers);
-                    }
-                    32 => {
-                        write_bench::<4>(b, n_readers);
-                    }
-                    64 => {
-                        write_bench::<8>(b, n_readers);
-                    }
-                    128 => {
-                        write_bench::<16>(b, n_readers);
-                    }
-                    256 => {
-                        write_bench::<32>(b, n_readers);
-                    }
-                    512 => {
-                        write_bench::<64>(b, n_readers);
-                    }
-                    1024 => {
-                        write_bench::<128>(b, n_readers);
-                    }
-                    2048 => {
-                        write_bench::<256>(b, n_readers);
-                    }
-                    4096 => {
-                        write_bench::<512>(b, n_readers);
-                    }
-                    _ => {}
-                },
-            );
-        }
+        write_sizes::<SeqlockKind>(&mut group, n_readers);
+        write_sizes::<DoubleKind>(&mut group, n_readers);
         group.finish();
     }
 }
@@ -232,13 +320,13 @@ impl<const N: usize> Default for TimingMessage<N> {
     }
 }
 
-fn latency_bench<const N_BYTES: usize>(b: &mut Bencher, n_contenders: usize) {
+fn latency_bench<K: LockKind, const N_BYTES: usize>(b: &mut Bencher, n_contenders: usize) {
     b.iter_custom(|iters| {
         std::thread::scope(|s| {
             let clock = quanta::Clock::new();
             clock.now();
 
-            let lock = Arc::new(code::Seqlock::default());
+            let lock = Arc::new(K::Lock::<TimingMessage<N_BYTES>>::default());
             let done = Arc::new(sync::atomic::AtomicBool::new(false));
             let done1 = done.clone();
             let lock1 = lock.clone();
@@ -313,43 +401,406 @@ fn latency_bench<const N_BYTES: usize>(b: &mut Bencher, n_contenders: usize) {
     });
 }
 
+fn latency_sizes<K: LockKind>(group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>, n_readers: usize) {
+    for size in [8, 30, 32, 60, 124, 252, 508, 1020, 2044, 4092].iter() {
+        group.throughput(criterion::Throughput::Bytes(*size as u64));
+        group.bench_with_input(
+            K::bench_id(*size),
+            size,
+            |b, &size| match size {
+                8 => {
+                    latency_bench::<K, 8>(b, n_readers);
+                }
+                30 => {
+                    latency_bench::<K, 30>(b, n_readers);
+                }
+                32 => {
+                    latency_bench::<K, 32>(b, n_readers);
+                }
+                60 => {
+                    latency_bench::<K, 60>(b, n_readers);
+                }
+                124 => {
+                    latency_bench::<K, 124>(b, n_readers);
+                }
+                252 => {
+                    latency_bench::<K, 252>(b, n_readers);
+                }
+                _ => {}
+            },
+        );
+    }
+}
+
 fn latency(c: &mut Criterion) {
     for n_readers in 0..8 {
         let mut group = c.benchmark_group(format!("latency_{}_readers", n_readers));
-        for size in [8, 30, 32, 60, 124, 252, 508, 1020, 2044, 4092].iter() {
-            group.throughput(criterion::Throughput::Bytes(*size as u64));
-            group.bench_with_input(
-                BenchmarkId::from_parameter(size),
-                size,
-                |b, &size| match size {
-                    8 => {
-                        latency_bench::<8>(b, n_readers);
+        latency_sizes::<SeqlockKind>(&mut group, n_readers);
+        latency_sizes::<DoubleKind>(&mut group, n_readers);
+        group.finish();
+    }
+}
+
+// Reports failed read attempts instead of time, so criterion shows retries per read
+struct Retries;
+impl Measurement for Retries {
+    type Intermediate = ();
+    type Value = u64;
+
+    fn start(&self) {}
+    fn end(&self, _: ()) -> u64 {
+        0
+    }
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+    fn zero(&self) -> u64 {
+        0
+    }
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+impl ValueFormatter for Retries {
+    fn scale_values(&self, _: f64, _: &mut [f64]) -> &'static str {
+        "retries"
+    }
+    fn scale_throughputs(&self, _: f64, throughput: &Throughput, values: &mut [f64]) -> &'static str {
+        match *throughput {
+            Throughput::Elements(n) => {
+                values.iter_mut().for_each(|v| *v /= n as f64);
+                "retries/elem"
+            }
+            _ => "retries",
+        }
+    }
+    fn scale_for_machines(&self, _: &mut [f64]) -> &'static str {
+        "retries"
+    }
+}
+
+// failed read attempts per newly written value, the other readers only keep the lock busy
+fn retries_bench<K: LockKind, const N_BYTES: usize>(b: &mut Bencher<Retries>, n_readers: usize) {
+    b.iter_custom(|iters| {
+        let lock = K::Lock::<TimingMessage<N_BYTES>>::default();
+        let lock = &lock;
+        let done = sync::atomic::AtomicBool::new(false);
+        let done = &done;
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 1 });
+                let mut m = TimingMessage::<N_BYTES>::default();
+                while !done.load(Ordering::Relaxed) {
+                    m.rdtscp = rdtscp();
+                    lock.write(&m);
+                }
+            });
+            for i in 1..n_readers {
+                s.spawn(move || {
+                    core_affinity::set_for_current(CoreId { id: 2 * i + 3 });
+                    let mut m = TimingMessage::<N_BYTES>::default();
+                    while !done.load(Ordering::Relaxed) {
+                        lock.read(&mut m);
                     }
-                    30 => {
-                        latency_bench::<30>(b, n_readers);
+                });
+            }
+            let out = s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 3 });
+                let mut m = TimingMessage::<N_BYTES>::default();
+                let mut last_t = 0;
+                let mut retries = 0;
+                for _ in 0..iters {
+                    loop {
+                        while !lock.try_read(&mut m) {
+                            retries += 1;
+                        }
+                        if m.rdtscp != last_t {
+                            last_t = m.rdtscp;
+                            break;
+                        }
                     }
-                    32 => {
-                        latency_bench::<32>(b, n_readers);
+                }
+                done.store(true, Ordering::Relaxed);
+                retries
+            });
+            out.join().unwrap()
+        })
+    });
+}
+
+fn retries_sizes<K: LockKind>(group: &mut criterion::BenchmarkGroup<Retries>, n_readers: usize) {
+    for size in [8, 60, 252] {
+        group.bench_function(K::bench_id(size), |b| match size {
+            8 => retries_bench::<K, 8>(b, n_readers),
+            60 => retries_bench::<K, 60>(b, n_readers),
+            _ => retries_bench::<K, 252>(b, n_readers),
+        });
+    }
+}
+
+fn retries(c: &mut Criterion<Retries>) {
+    for n_readers in [1, 2, 4] {
+        let mut group = c.benchmark_group(format!("retries_{}_readers", n_readers));
+        retries_sizes::<SeqlockKind>(&mut group, n_readers);
+        retries_sizes::<DoubleKind>(&mut group, n_readers);
+        group.finish();
+    }
+}
+
+// the payload is the rdtscp of the write, the other readers only keep the lock busy
+fn small_latency_bench(b: &mut Bencher, lock: &impl Lock<u64>, n_readers: usize) {
+    b.iter_custom(|iters| {
+        let clock = quanta::Clock::new();
+        clock.now();
+        let done = sync::atomic::AtomicBool::new(false);
+        let done = &done;
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 1 });
+                while !done.load(Ordering::Relaxed) {
+                    lock.write(&rdtscp());
+                }
+            });
+            for i in 1..n_readers {
+                s.spawn(move || {
+                    core_affinity::set_for_current(CoreId { id: 2 * i + 3 });
+                    let mut m = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        lock.read(&mut m);
                     }
-                    60 => {
-                        latency_bench::<60>(b, n_readers);
+                });
+            }
+            let out = s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 3 });
+                let mut m = 0;
+                let mut last_t = 0;
+                let mut lat = 0;
+                for _ in 0..iters {
+                    loop {
+                        lock.read(&mut m);
+                        let now = rdtscp();
+                        if m != last_t {
+                            last_t = m;
+                            lat += now.saturating_sub(m);
+                            break;
+                        }
                     }
-                    124 => {
-                        latency_bench::<124>(b, n_readers);
+                }
+                done.store(true, Ordering::Relaxed);
+                Duration::from_nanos(clock.delta_as_nanos(0, lat))
+            });
+            out.join().unwrap()
+        })
+    });
+}
+
+fn small_latency(c: &mut Criterion) {
+    for n_readers in [1, 2, 4] {
+        let mut group = c.benchmark_group(format!("small_latency_{}_readers", n_readers));
+        let seqlock = code::Seqlock::new(0u64);
+        let small = code::SmallSeqlock::new(0u64);
+        group.bench_function("seqlock", |b| small_latency_bench(b, &seqlock, n_readers));
+        group.bench_function("small", |b| small_latency_bench(b, &small, n_readers));
+        group.finish();
+    }
+}
+
+// time per read while the writer republishes the same value 15 out of 16 times
+fn noop_write_bench(b: &mut Bencher, skip: bool) {
+    b.iter_custom(|iters| {
+        let clock = quanta::Clock::new();
+        clock.now();
+        let lock = code::Seqlock::new([0u64; 8]);
+        let lock = &lock;
+        let done = sync::atomic::AtomicBool::new(false);
+        let done = &done;
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 1 });
+                let mut m = [0u64; 8];
+                let mut c = 0u64;
+                while !done.load(Ordering::Relaxed) {
+                    c += 1;
+                    if c & 15 == 0 {
+                        m[0] += 1;
                     }
-                    252 => {
-                        latency_bench::<252>(b, n_readers);
+                    if skip {
+                        lock.write_if_changed(&m);
+                    } else {
+                        lock.write(&m);
                     }
-                    _ => {}
-                },
-            );
+                    let last_write = rdtscp();
+                    while rdtscp() - last_write < 100 {}
+                }
+            });
+            let out = s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 3 });
+                let mut m = [0u64; 8];
+                let start = rdtscp();
+                for _ in 0..iters {
+                    lock.read(&mut m);
+                }
+                let cycles = rdtscp() - start;
+                done.store(true, Ordering::Relaxed);
+                Duration::from_nanos(clock.delta_as_nanos(0, cycles))
+            });
+            out.join().unwrap()
+        })
+    });
+}
+
+fn noop_writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("noop_writes");
+    group.bench_function("write", |b| noop_write_bench(b, false));
+    group.bench_function("write_if_changed", |b| noop_write_bench(b, true));
+    group.finish();
+}
+
+fn boxed_zeroed<T>() -> Box<T> {
+    unsafe { Box::from_raw(std::alloc::alloc_zeroed(std::alloc::Layout::new::<T>()) as *mut T) }
+}
+
+// time per successful read while a writer rewrites the whole payload, pausing for about as long
+// as a read takes in between
+fn large_read_bench<const N_BYTES: usize>(b: &mut Bencher, chunked: bool) {
+    b.iter_custom(|iters| {
+        let clock = quanta::Clock::new();
+        clock.now();
+        let lock: Box<code::Seqlock<[u8; N_BYTES]>> = boxed_zeroed();
+        let lock = lock.as_ref();
+        let done = sync::atomic::AtomicBool::new(false);
+        let done = &done;
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 1 });
+                let mut m: Box<[u8; N_BYTES]> = boxed_zeroed();
+                let mut c = 0u8;
+                while !done.load(Ordering::Relaxed) {
+                    c = c.wrapping_add(1);
+                    m[0] = c;
+                    lock.write(&m);
+                    let last_write = rdtscp();
+                    while rdtscp() - last_write < N_BYTES as u64 / 8 {}
+                }
+            });
+            let out = s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 3 });
+                let mut m: Box<[u8; N_BYTES]> = boxed_zeroed();
+                let start = rdtscp();
+                for _ in 0..iters {
+                    if chunked {
+                        lock.read_chunked(&mut m);
+                    } else {
+                        lock.read(&mut m);
+                    }
+                }
+                let cycles = rdtscp() - start;
+                done.store(true, Ordering::Relaxed);
+                Duration::from_nanos(clock.delta_as_nanos(0, cycles))
+            });
+            out.join().unwrap()
+        })
+    });
+}
+
+fn large_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large_read");
+    for size in [4096, 65536, 262144, 1048576] {
+        group.throughput(criterion::Throughput::Bytes(size as u64));
+        for (name, chunked) in [("read", false), ("read_chunked", true)] {
+            group.bench_with_input(BenchmarkId::new(name, size), &size, |b, &size| match size {
+                4096 => large_read_bench::<4096>(b, chunked),
+                65536 => large_read_bench::<65536>(b, chunked),
+                262144 => large_read_bench::<262144>(b, chunked),
+                1048576 => large_read_bench::<1048576>(b, chunked),
+                _ => {}
+            });
         }
-        group.finish();
     }
+    group.finish();
 }
+
+// only looks at the ends of the payload through the guard instead of copying it
+struct RcuGuarded<const N: usize>(code::RcuCell<[u8; N]>);
+
+impl<const N: usize> Lock<[u8; N]> for RcuGuarded<N> {
+    fn write(&self, val: &[u8; N]) {
+        self.0.write(val)
+    }
+    fn read(&self, out: &mut [u8; N]) {
+        let g = self.0.load();
+        out[0] = g[0];
+        out[N - 1] = g[N - 1];
+    }
+}
+
+// time per read while a writer keeps replacing the payload
+fn rcu_bench<const N_BYTES: usize>(b: &mut Bencher, lock: &impl Lock<[u8; N_BYTES]>) {
+    b.iter_custom(|iters| {
+        let clock = quanta::Clock::new();
+        clock.now();
+        let done = sync::atomic::AtomicBool::new(false);
+        let done = &done;
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 1 });
+                let mut m: Box<[u8; N_BYTES]> = boxed_zeroed();
+                let mut c = 0u8;
+                while !done.load(Ordering::Relaxed) {
+                    c = c.wrapping_add(1);
+                    m[0] = c;
+                    lock.write(&m);
+                    let last_write = rdtscp();
+                    while rdtscp() - last_write < N_BYTES as u64 / 8 {}
+                }
+            });
+            let out = s.spawn(move || {
+                core_affinity::set_for_current(CoreId { id: 3 });
+                let mut m: Box<[u8; N_BYTES]> = boxed_zeroed();
+                let start = rdtscp();
+                for _ in 0..iters {
+                    lock.read(&mut m);
+                }
+                let cycles = rdtscp() - start;
+                done.store(true, Ordering::Relaxed);
+                Duration::from_nanos(clock.delta_as_nanos(0, cycles))
+            });
+            out.join().unwrap()
+        })
+    });
+}
+
+fn rcu_sizes<const N_BYTES: usize>(group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>) {
+    group.throughput(criterion::Throughput::Bytes(N_BYTES as u64));
+    let seqlock: Box<code::Seqlock<[u8; N_BYTES]>> = boxed_zeroed();
+    let rcu = code::RcuCell::new([0u8; N_BYTES]);
+    let guarded = RcuGuarded(code::RcuCell::new([0u8; N_BYTES]));
+    group.bench_function(BenchmarkId::new("seqlock", N_BYTES), |b| rcu_bench(b, seqlock.as_ref()));
+    group.bench_function(BenchmarkId::new("rcu_copy", N_BYTES), |b| rcu_bench(b, &rcu));
+    group.bench_function(BenchmarkId::new("rcu_guard", N_BYTES), |b| rcu_bench(b, &guarded));
+}
+
+fn rcu_vs_seqlock(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rcu_vs_seqlock");
+    rcu_sizes::<64>(&mut group);
+    rcu_sizes::<512>(&mut group);
+    rcu_sizes::<4096>(&mut group);
+    rcu_sizes::<65536>(&mut group);
+    rcu_sizes::<1048576>(&mut group);
+    group.finish();
+}
+
 criterion_group! {
     name=seqlock;
     config=Criterion::default().sample_size(2000).measurement_time(std::time::Duration::from_secs(10));
-    targets = write, read, latency
+    targets = write, read, latency, large_read, rcu_vs_seqlock, small_latency, noop_writes
+}
+criterion_group! {
+    name=read_retries;
+    config=Criterion::default().with_measurement(Retries);
+    targets = retries
 }
-criterion_main!(seqlock);
+criterion_main!(seqlock, read_retries);
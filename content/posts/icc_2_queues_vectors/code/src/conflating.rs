@@ -0,0 +1,220 @@
+use crate::queue::{Consumer, Producer, Queue, QueueError, QueueType};
+use crate::seqlock::ReadError;
+use crate::vector::SeqlockVector;
+use crate::wait::WaitStrategy;
+
+/// Latest value per key: producers overwrite the key's slot in a `SeqlockVector` and push the key
+/// on a ring of dirty keys. Consumers skip notifications for values they already saw, and rescan
+/// the whole vector if the ring laps them, so they can miss intermediate values but never the last one.
+/// Keys are indices into the vector, i.e. `0..n_keys`.
+pub struct ConflatingQueue<K: 'static, T: 'static> {
+    values: &'static SeqlockVector<T>,
+    dirty:  &'static Queue<K>,
+}
+
+impl<K: Copy + 'static, T: Copy + 'static> ConflatingQueue<K, T> {
+    /// `ring_len` has to be a power of two, a longer ring means fewer rescans of slow consumers
+    pub fn new(n_keys: usize, ring_len: usize) -> Result<Self, QueueError> {
+        Ok(Self {
+            values: SeqlockVector::new(n_keys),
+            dirty:  Queue::new(ring_len, QueueType::MPMC)?,
+        })
+    }
+
+    pub fn n_keys(&self) -> usize {
+        self.values.len()
+    }
+}
+
+pub struct ConflatingProducer<'a, K: 'static, T: 'static> {
+    queue: &'a ConflatingQueue<K, T>,
+    dirty: Producer<'static, K>,
+}
+
+impl<'a, K: Copy + 'static, T: Copy> From<&'a ConflatingQueue<K, T>> for ConflatingProducer<'a, K, T> {
+    fn from(queue: &'a ConflatingQueue<K, T>) -> Self {
+        Self {
+            queue,
+            dirty: Producer::from(queue.dirty),
+        }
+    }
+}
+
+impl<'a, K: Copy + Into<usize> + 'static, T: Copy> ConflatingProducer<'a, K, T> {
+    // the value is written before the key is pushed, so whoever sees the key sees the value.
+    // Other producers may write the same key, so the write has to take the slot's lock.
+    pub fn produce(&mut self, key: K, value: &T) {
+        self.queue.values.write_multi(key.into(), value);
+        self.dirty.produce(&key);
+    }
+}
+
+pub struct ConflatingConsumer<'a, K: 'static, T: 'static> {
+    queue:    &'a ConflatingQueue<K, T>,
+    dirty:    Consumer<'static, K>,
+    // version of every key's slot as of our last read of it
+    versions: Vec<usize>,
+    // next key to check while rescanning after the ring lapped us
+    rescan:   Option<usize>,
+}
+
+impl<'a, K: Copy + 'static, T: Copy> From<&'a ConflatingQueue<K, T>> for ConflatingConsumer<'a, K, T> {
+    fn from(queue: &'a ConflatingQueue<K, T>) -> Self {
+        Self {
+            queue,
+            dirty: Consumer::from(queue.dirty),
+            versions: vec![0; queue.n_keys()],
+            rescan: None,
+        }
+    }
+}
+
+impl<'a, K, T> ConflatingConsumer<'a, K, T>
+where
+    K: Copy + Default + Into<usize> + TryFrom<usize> + 'static,
+    T: Copy,
+{
+    /// Writes the latest value of the next changed key into `el` and returns the key,
+    /// `None` if no key changed since we last read it
+    pub fn try_consume(&mut self, el: &mut T) -> Option<K> {
+        loop {
+            if let Some(start) = self.rescan {
+                for i in start..self.versions.len() {
+                    if self.queue.values.read_if_changed(i, el, &mut self.versions[i]) {
+                        self.rescan = Some(i + 1);
+                        return Some(K::try_from(i).ok().expect("Key doesn't fit in K"));
+                    }
+                }
+                self.rescan = None;
+            }
+            let mut key = K::default();
+            match self.dirty.try_consume(&mut key) {
+                Ok(()) => {
+                    let i = key.into();
+                    // older notifications of a key we already read the latest value of are skipped
+                    if self.queue.values.read_if_changed(i, el, &mut self.versions[i]) {
+                        return Some(key);
+                    }
+                }
+                Err(ReadError::Empty) => return None,
+                Err(ReadError::SpedPast) => {
+                    // keys pushed from here on get delivered through the ring, anything before is
+                    // already in the vector
+                    self.dirty = Consumer::from(self.queue.dirty);
+                    self.rescan = Some(0);
+                }
+            }
+        }
+    }
+
+    /// Blocking consume, calling `wait` for as long as no key changed
+    pub fn consume(&mut self, el: &mut T, wait: &impl WaitStrategy) -> K {
+        let mut n = 0;
+        loop {
+            if let Some(key) = self.try_consume(el) {
+                return key;
+            }
+            wait.wait(n);
+            n += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wait::Yield;
+
+    #[test]
+    fn conflation() {
+        let q = ConflatingQueue::<usize, usize>::new(4, 8).unwrap();
+        let mut p = ConflatingProducer::from(&q);
+        let mut c = ConflatingConsumer::from(&q);
+        let mut m = 0;
+        assert_eq!(c.try_consume(&mut m), None);
+        for i in 0..3 {
+            p.produce(1, &i);
+            p.produce(2, &(10 + i));
+        }
+        assert_eq!(c.try_consume(&mut m), Some(1));
+        assert_eq!(m, 2);
+        assert_eq!(c.try_consume(&mut m), Some(2));
+        assert_eq!(m, 12);
+        // the remaining notifications are for values we already have
+        assert_eq!(c.try_consume(&mut m), None);
+
+        // lapped by the ring: a rescan still delivers the last value of every changed key
+        for i in 0..20 {
+            p.produce(i % 3, &(100 + i));
+        }
+        let mut got = Vec::new();
+        while let Some(k) = c.try_consume(&mut m) {
+            got.push((k, m));
+        }
+        got.sort();
+        assert_eq!(got, [(0, 118), (1, 119), (2, 117)]);
+    }
+
+    #[test]
+    fn shared_key() {
+        const N_PER_PRODUCER: usize = 20000;
+        let q = ConflatingQueue::<usize, [usize; 8]>::new(1, 64).unwrap();
+        let mut c = ConflatingConsumer::from(&q);
+        std::thread::scope(|s| {
+            for n in 1..=2 {
+                let q = &q;
+                s.spawn(move || {
+                    let mut p = ConflatingProducer::from(q);
+                    for i in 0..N_PER_PRODUCER {
+                        p.produce(0, &[n * i; 8]);
+                    }
+                });
+            }
+            let mut m = [0; 8];
+            while q.values.slot_version(0) < 4 * N_PER_PRODUCER {
+                if c.try_consume(&mut m).is_some() {
+                    assert!(m.iter().all(|&x| x == m[0]));
+                }
+            }
+        });
+        // no write got lost or left the version odd
+        assert_eq!(q.values.slot_version(0), 4 * N_PER_PRODUCER);
+    }
+
+    #[test]
+    fn bursty_producers() {
+        const N_KEYS: usize = 64;
+        const N_PER_PRODUCER: usize = 50000;
+        let q = ConflatingQueue::<u16, (usize, usize)>::new(N_KEYS, 64).unwrap();
+        let mut last = vec![(0, 0); N_KEYS];
+        let mut c = ConflatingConsumer::from(&q);
+        std::thread::scope(|s| {
+            for n in 0..2 {
+                let q = &q;
+                s.spawn(move || {
+                    let mut p = ConflatingProducer::from(q);
+                    // every producer owns half of the keys, so the final value per key is known
+                    for i in 1..=N_PER_PRODUCER {
+                        let key = (2 * (i % (N_KEYS / 2)) + n) as u16;
+                        p.produce(key, &(i, n));
+                        if i % 1000 == 0 {
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+            let mut m = (0, 0);
+            while last.iter().filter(|(i, _)| *i + N_KEYS / 2 > N_PER_PRODUCER).count() < N_KEYS {
+                let key = c.consume(&mut m, &Yield) as usize;
+                assert_eq!(key % 2, m.1);
+                // values of a key only ever move forward
+                assert!(m.0 > last[key].0);
+                last[key] = m;
+            }
+        });
+        for (key, (i, n)) in last.into_iter().enumerate() {
+            assert_eq!(n, key % 2);
+            assert_eq!(2 * (i % (N_KEYS / 2)) + n, key);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::{
+    ptr,
+    sync::atomic::AtomicU32,
+    time::Duration,
+};
+
+// No FUTEX_PRIVATE_FLAG: the word may live in shared memory and be waited on from other processes
+
+/// Sleeps as long as `word` holds `expected`, until woken or `timeout` passes
+pub fn wait(word: &AtomicU32, expected: u32, timeout: Duration) {
+    let ts = libc::timespec {
+        tv_sec:  timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            expected,
+            &ts as *const libc::timespec,
+            ptr::null::<u32>(),
+            0u32,
+        );
+    }
+}
+
+/// Wakes everyone sleeping on `word`
+pub fn wake_all(word: &AtomicU32) {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAKE,
+            i32::MAX,
+            ptr::null::<libc::timespec>(),
+            ptr::null::<u32>(),
+            0u32,
+        );
+    }
+}
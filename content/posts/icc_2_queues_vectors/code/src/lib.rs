@@ -1,6 +1,24 @@
 pub mod seqlock;
+pub mod small_seqlock;
 pub mod vector;
 pub mod queue;
-pub use seqlock::Seqlock;
+pub mod wait;
+pub mod conflating;
+pub mod priority;
+pub mod poller;
+pub mod stamped;
+pub mod trace;
+pub mod var_queue;
+pub mod rcu;
+mod futex;
+pub use seqlock::{DoubleSeqlock, Seqlock, SeqlockSlice, Slot};
+pub use small_seqlock::SmallSeqlock;
 pub use queue::Queue;
-pub use vector::SeqlockVector;
+pub use vector::{DynSeqlockVector, SeqlockVector};
+pub use wait::WaitStrategy;
+pub use conflating::ConflatingQueue;
+pub use priority::PriorityConsumer;
+pub use poller::Poller;
+pub use stamped::Stamped;
+pub use var_queue::VarQueue;
+pub use rcu::RcuCell;
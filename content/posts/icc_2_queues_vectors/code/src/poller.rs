@@ -0,0 +1,174 @@
+use crate::queue::Consumer;
+use crate::wait::WaitStrategy;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStats {
+    pub handled:   usize,
+    pub sped_past: usize,
+}
+
+trait Source {
+    // handles up to n ready messages, returning how many
+    fn poll(&mut self, n: usize) -> usize;
+    fn stats(&self) -> SourceStats;
+}
+
+struct ConsumerSource<'a, T, F> {
+    consumer: Consumer<'a, T>,
+    handler:  F,
+    stats:    SourceStats,
+}
+
+impl<'a, T: Copy, F: FnMut(&T)> Source for ConsumerSource<'a, T, F> {
+    fn poll(&mut self, n: usize) -> usize {
+        let mut handled = 0;
+        while handled < n {
+            match self.consumer.try_iter().next() {
+                Some(Ok(msg)) => {
+                    (self.handler)(&msg);
+                    handled += 1;
+                }
+                // the iterator only yields SpedPast
+                Some(Err(_)) => {
+                    self.stats.sped_past += 1;
+                    self.consumer.resync();
+                }
+                None => break,
+            }
+        }
+        self.stats.handled += handled;
+        handled
+    }
+
+    fn stats(&self) -> SourceStats {
+        self.stats
+    }
+}
+
+/// Polls consumers of queues with different message types, handing every message to the handler
+/// registered with its consumer. Each round polls every source once, starting one further every round,
+/// and handles up to `weight` messages of it. Sources that got sped past continue with the newest message.
+#[derive(Default)]
+pub struct Poller<'a> {
+    sources: Vec<(Box<dyn Source + 'a>, usize)>,
+    next:    usize,
+}
+
+impl<'a> Poller<'a> {
+    /// Returns the id of the source, to be used with `stats`
+    pub fn add<T, F>(&mut self, consumer: Consumer<'a, T>, weight: usize, handler: F) -> usize
+    where
+        T: Copy + 'a,
+        F: FnMut(&T) + 'a,
+    {
+        assert!(weight > 0, "A source with weight 0 never gets polled");
+        self.sources.push((
+            Box::new(ConsumerSource {
+                consumer,
+                handler,
+                stats: SourceStats::default(),
+            }),
+            weight,
+        ));
+        self.sources.len() - 1
+    }
+
+    /// One round over all sources, returning the number of handled messages
+    pub fn poll(&mut self) -> usize {
+        let n = self.sources.len();
+        if n == 0 {
+            return 0;
+        }
+        let mut handled = 0;
+        for i in 0..n {
+            let (source, weight) = &mut self.sources[(self.next + i) % n];
+            handled += source.poll(*weight);
+        }
+        self.next = (self.next + 1) % n;
+        handled
+    }
+
+    /// Polls rounds, calling `wait` in between, until at least one message was handled
+    pub fn poll_blocking(&mut self, wait: &impl WaitStrategy) -> usize {
+        let mut n = 0;
+        loop {
+            let handled = self.poll();
+            if handled != 0 {
+                return handled;
+            }
+            wait.wait(n);
+            n += 1;
+        }
+    }
+
+    pub fn stats(&self, id: usize) -> SourceStats {
+        self.sources[id].0.stats()
+    }
+
+    pub fn all_stats(&self) -> Vec<SourceStats> {
+        self.sources.iter().map(|(s, _)| s.stats()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Producer, Queue, QueueType};
+    use crate::wait::Yield;
+    use std::cell::RefCell;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Event {
+        Price(f64),
+        Halt,
+    }
+
+    #[test]
+    fn weighted() {
+        let prices = Queue::new(16, QueueType::SPMC).unwrap();
+        let ids = Queue::<[u8; 4]>::new(16, QueueType::SPMC).unwrap();
+        let seen = RefCell::new(Vec::new());
+        let mut poller = Poller::default();
+        let p_id = poller.add(Consumer::from(prices), 3, |m: &f64| {
+            seen.borrow_mut().push(Event::Price(*m))
+        });
+        let i_id = poller.add(Consumer::from(ids), 1, |m: &[u8; 4]| {
+            assert_eq!(m, &[1; 4]);
+            seen.borrow_mut().push(Event::Halt)
+        });
+        assert_eq!(poller.poll(), 0);
+
+        let mut pp = Producer::from(prices);
+        let mut pi = Producer::from(ids);
+        for i in 0..5 {
+            pp.produce(&(i as f64));
+            pi.produce(&[1; 4]);
+        }
+        // the empty round started at prices, so this one starts at the ids
+        assert_eq!(poller.poll(), 4);
+        assert_eq!(poller.poll(), 3);
+        assert_eq!(
+            *seen.borrow(),
+            [
+                Event::Halt,
+                Event::Price(0.0),
+                Event::Price(1.0),
+                Event::Price(2.0),
+                Event::Price(3.0),
+                Event::Price(4.0),
+                Event::Halt,
+            ]
+        );
+        assert_eq!(poller.stats(p_id), SourceStats { handled: 5, sped_past: 0 });
+        assert_eq!(poller.stats(i_id), SourceStats { handled: 2, sped_past: 0 });
+
+        // lapped sources are counted and resynced
+        for _ in 0..20 {
+            pi.produce(&[1; 4]);
+        }
+        assert_eq!(poller.poll(), 0);
+        pi.produce(&[1; 4]);
+        assert_eq!(poller.poll_blocking(&Yield), 1);
+        assert_eq!(poller.all_stats()[i_id], SourceStats { handled: 3, sped_past: 1 });
+    }
+}
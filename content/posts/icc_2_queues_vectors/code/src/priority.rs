@@ -0,0 +1,180 @@
+use crate::queue::{Consumer, Queue};
+use crate::seqlock::ReadError;
+use crate::wait::WaitStrategy;
+
+struct Lane<'a, T> {
+    consumer: Consumer<'a, T>,
+    priority: u32,
+    // messages served from higher lanes since this one was last found empty or served
+    waited:   usize,
+}
+
+/// Reads from several queues, always draining the lane with the highest priority first.
+/// A lower lane is served anyway once `starvation_bound` messages of higher lanes were read
+/// while it might have had one waiting, so bulk data keeps trickling through a flood of control messages.
+pub struct PriorityConsumer<'a, T> {
+    // sorted by descending priority
+    lanes:            Vec<Lane<'a, T>>,
+    starvation_bound: usize,
+}
+
+impl<'a, T: Copy> Default for PriorityConsumer<'a, T> {
+    fn default() -> Self {
+        Self {
+            lanes:            Vec::new(),
+            starvation_bound: usize::MAX,
+        }
+    }
+}
+
+impl<'a, T: Copy> PriorityConsumer<'a, T> {
+    /// Lanes with the same priority are drained in the order they were added
+    pub fn with_lane(mut self, queue: &'a Queue<T>, priority: u32) -> Self {
+        let i = self.lanes.partition_point(|l| l.priority >= priority);
+        self.lanes.insert(
+            i,
+            Lane {
+                consumer: Consumer::from(queue),
+                priority,
+                waited: 0,
+            },
+        );
+        self
+    }
+
+    pub fn with_starvation_bound(mut self, n: usize) -> Self {
+        self.starvation_bound = n;
+        self
+    }
+
+    // Lower lanes get their waited count bumped without checking whether they have something,
+    // it's reset when they turn out to be empty. Checking would cost a read of their slot per message.
+    fn try_lane(&mut self, i: usize, el: &mut T) -> Result<u32, ReadError> {
+        let lane = &mut self.lanes[i];
+        match lane.consumer.try_consume(el) {
+            Ok(()) => {
+                lane.waited = 0;
+                let priority = lane.priority;
+                for l in &mut self.lanes[i + 1..] {
+                    l.waited = l.waited.saturating_add(1);
+                }
+                Ok(priority)
+            }
+            Err(ReadError::Empty) => {
+                lane.waited = 0;
+                Err(ReadError::Empty)
+            }
+            Err(ReadError::SpedPast) => {
+                // report it once and carry on from the newest message
+                lane.consumer.resync();
+                Err(ReadError::SpedPast)
+            }
+        }
+    }
+
+    /// Reads the next message, returning the priority of the lane it came from.
+    /// `ReadError::SpedPast` means a lane lost messages, the next call continues with the newest ones.
+    pub fn try_consume(&mut self, el: &mut T) -> Result<u32, ReadError> {
+        // the lowest starved lane waited longest
+        if let Some(i) = self.lanes.iter().rposition(|l| l.waited >= self.starvation_bound) {
+            match self.try_lane(i, el) {
+                Err(ReadError::Empty) => {}
+                r => return r,
+            }
+        }
+        for i in 0..self.lanes.len() {
+            match self.try_lane(i, el) {
+                Err(ReadError::Empty) => {}
+                r => return r,
+            }
+        }
+        Err(ReadError::Empty)
+    }
+
+    /// Blocking consume, calling `wait` for as long as all lanes are empty.
+    /// Only returns an error if a lane got sped past.
+    pub fn consume(&mut self, el: &mut T, wait: &impl WaitStrategy) -> Result<u32, ReadError> {
+        let mut n = 0;
+        loop {
+            match self.try_consume(el) {
+                Err(ReadError::Empty) => {
+                    wait.wait(n);
+                    n += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Producer, QueueType};
+
+    #[test]
+    fn priorities() {
+        let bulk = Queue::new(16, QueueType::SPMC).unwrap();
+        let control = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut c = PriorityConsumer::default()
+            .with_lane(bulk, 0)
+            .with_lane(control, 10);
+        let mut pb = Producer::from(bulk);
+        let mut pc = Producer::from(control);
+        let mut m = 0;
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+        for i in 0..4 {
+            pb.produce(&i);
+        }
+        assert_eq!(c.try_consume(&mut m), Ok(0));
+        assert_eq!(m, 0);
+        pc.produce(&100);
+        pc.produce(&101);
+        assert_eq!(c.try_consume(&mut m), Ok(10));
+        assert_eq!(m, 100);
+        assert_eq!(c.try_consume(&mut m), Ok(10));
+        assert_eq!(m, 101);
+        assert_eq!(c.try_consume(&mut m), Ok(0));
+        assert_eq!(m, 1);
+
+        // lapped lanes report it once and continue with new messages
+        for i in 0..20 {
+            pb.produce(&i);
+        }
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::SpedPast));
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+        pb.produce(&20);
+        assert_eq!(c.try_consume(&mut m), Ok(0));
+        assert_eq!(m, 20);
+    }
+
+    #[test]
+    fn starvation_bound() {
+        let bulk = Queue::new(64, QueueType::SPMC).unwrap();
+        let mid = Queue::new(64, QueueType::SPMC).unwrap();
+        let control = Queue::new(64, QueueType::SPMC).unwrap();
+        let mut c = PriorityConsumer::default()
+            .with_lane(control, 2)
+            .with_lane(bulk, 0)
+            .with_lane(mid, 1)
+            .with_starvation_bound(3);
+        let mut pb = Producer::from(bulk);
+        let mut pm = Producer::from(mid);
+        let mut pc = Producer::from(control);
+        for i in 0..4 {
+            pb.produce(&i);
+            pm.produce(&(10 + i));
+        }
+        for i in 0..12 {
+            pc.produce(&(100 + i));
+        }
+        let mut m = 0;
+        let order: Vec<_> = (0..20).map(|_| c.try_consume(&mut m).unwrap()).collect();
+        // at most 3 messages of higher lanes get read while a lower one waits,
+        // the lowest starved lane goes first
+        assert_eq!(
+            order,
+            [2, 2, 2, 0, 1, 2, 2, 0, 2, 1, 2, 0, 2, 2, 1, 0, 2, 2, 2, 1]
+        );
+    }
+}
@@ -1,7 +1,9 @@
-use std::{alloc::Layout,  mem::size_of, sync::atomic::{AtomicUsize, Ordering}};
+use std::{alloc::Layout, arch::x86_64::_rdtsc,  marker::PhantomData, mem::{size_of, MaybeUninit}, ops::Range, sync::atomic::{fence, AtomicU32, AtomicU64, AtomicUsize, Ordering}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
 use thiserror::Error;
-use crate::seqlock::{ReadError, Seqlock};
+use crate::seqlock::{ReadError, Seqlock, Slot};
+use crate::futex;
+use crate::wait::{BusySpin, WaitStrategy};
 
 #[derive(Error, Debug)]
 pub enum QueueError {
@@ -11,6 +13,18 @@ pub enum QueueError {
     LengthNotPowerOfTwo,
     #[error("Element size not power of two - 4")]
     ElementSizeNotPowerTwo,
+    #[error("Queue full")]
+    Full,
+    #[error("Number of shards not a power of two, larger than MAX_SHARDS or larger than the queue")]
+    InvalidShards,
+    #[error("Group id not smaller than MAX_GROUPS")]
+    InvalidGroup,
+    #[error("Message larger than the queue")]
+    MessageTooLarge,
+    #[error("All shards already claimed by other producers")]
+    ShardsClaimed,
+    #[error("Queue smaller than a record header")]
+    SizeTooSmall,
     #[cfg(feature = "shmem")]
     #[error("Shmem error")]
     SharedMemoryError(#[from] shared_memory::ShmemError),
@@ -22,6 +36,84 @@ pub enum QueueType {
     Unknown,
     MPMC,
     SPMC,
+    /// Every producer owns a sub-ring, see `Queue::new_sharded`
+    Sharded,
+    /// Bounded and lossless: the single consumer publishes its position and producers
+    /// get `QueueError::Full` instead of overwriting unread messages
+    SPSC,
+    MPSC,
+}
+
+pub const MAX_SHARDS: usize = 16;
+pub const MAX_CONSUMERS: usize = 16;
+pub const MAX_GROUPS: usize = 8;
+/// Consumers publish their sequence every this many messages, and whenever they run out of messages
+pub const DEFAULT_PUBLISH_EVERY: usize = 64;
+
+/// A count on its own cache line, so its writer doesn't false share with anyone
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+pub struct PaddedCount(AtomicUsize);
+
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+struct ConsumerSlot {
+    sequence:   AtomicUsize,    // 8
+    heartbeat:  AtomicU64,      // 16 nanos since the unix epoch, refreshed by publishes every HEARTBEAT_CYCLES
+    pid:        AtomicU32,      // 20
+    // bumped whenever a consumer claims the slot, so handles of previous owners can be told apart
+    generation: AtomicU32,      // 24
+    name:       [AtomicU64; 4], // 56 utf8, zero padded
+    // tsc of the last heartbeat
+    beat_tsc:   AtomicU64,      // 64
+}
+
+// a few ms, so publishing only pays for a rdtsc instead of reading the system clock
+const HEARTBEAT_CYCLES: u64 = 1 << 24;
+
+impl ConsumerSlot {
+    fn set_name(&self, name: &str) {
+        let mut bytes = [0u8; 32];
+        let n = name.len().min(32);
+        bytes[..n].copy_from_slice(&name.as_bytes()[..n]);
+        for (w, chunk) in self.name.iter().zip(bytes.chunks_exact(8)) {
+            w.store(u64::from_ne_bytes(chunk.try_into().unwrap()), Ordering::Relaxed);
+        }
+    }
+
+    fn name(&self) -> String {
+        let bytes: Vec<u8> = self
+            .name
+            .iter()
+            .flat_map(|w| w.load(Ordering::Relaxed).to_ne_bytes())
+            .take_while(|&b| b != 0)
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    fn publish(&self, sequence: usize) {
+        let tsc = unsafe { _rdtsc() };
+        if tsc.wrapping_sub(self.beat_tsc.load(Ordering::Relaxed)) > HEARTBEAT_CYCLES {
+            self.beat(tsc);
+        }
+        self.sequence.store(sequence, Ordering::Release);
+    }
+
+    fn beat(&self, tsc: u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        self.heartbeat.store(now, Ordering::Relaxed);
+        self.beat_tsc.store(tsc, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+struct GroupSlot {
+    // count + 1 of the next message to be claimed, 0 until the first member joins
+    claim:   AtomicUsize,
+    members: AtomicUsize,
 }
 
 #[derive(Debug)]
@@ -29,10 +121,18 @@ pub enum QueueType {
 pub struct QueueHeader {
     pub queue_type:         QueueType,   // 1
     pub is_initialized:     u8,          // 2
-    _pad1:                  [u8; 2],          // 4
+    pub n_shards:           u16,         // 4
     pub elsize:             u32,         // 8
     mask:                   usize,       // 16
     pub count:              AtomicUsize, // 24
+    futex:                  AtomicU32,   // 28 bumped by producers when n_waiters > 0
+    n_waiters:              AtomicU32,   // 32
+    shards_claimed:         AtomicU64,   // 40 bitmask of shards owned by a producer
+    consumers_claimed:      AtomicU64,   // 48 bitmask of registered consumers
+    read_count:             PaddedCount, // 128 published by the consumer of bounded queues
+    shard_counts:           [PaddedCount; MAX_SHARDS], // 128 + 16 * 64
+    consumers:              [ConsumerSlot; MAX_CONSUMERS], // 1152 + 16 * 64 published by registered consumers
+    groups:                 [GroupSlot; MAX_GROUPS], // 2176 + 8 * 64
 }
 impl QueueHeader {
     /// in bytes
@@ -47,6 +147,91 @@ impl QueueHeader {
     pub fn from_ptr(ptr: *mut u8) -> &'static mut Self {
         unsafe { &mut *(ptr as *mut Self) }
     }
+
+    fn claim_shard(&self) -> Option<usize> {
+        let all = (1u64 << self.n_shards) - 1;
+        self.shards_claimed
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |claimed| {
+                let free = !claimed & all;
+                (free != 0).then(|| claimed | (1 << free.trailing_zeros()))
+            })
+            .ok()
+            .map(|claimed| (!claimed & all).trailing_zeros() as usize)
+    }
+
+    fn release_shard(&self, shard: usize) {
+        self.shards_claimed.fetch_and(!(1 << shard), Ordering::AcqRel);
+    }
+
+    fn claim_consumer(&self) -> Option<usize> {
+        let all = u64::MAX >> (64 - MAX_CONSUMERS);
+        self.consumers_claimed
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |claimed| {
+                let free = !claimed & all;
+                (free != 0).then(|| claimed | (1 << free.trailing_zeros()))
+            })
+            .ok()
+            .map(|claimed| (!claimed & all).trailing_zeros() as usize)
+    }
+
+    fn release_consumer(&self, slot: usize) {
+        self.consumers_claimed.fetch_and(!(1 << slot), Ordering::AcqRel);
+    }
+
+    /// The published sequence of a registered consumer, i.e. the count of the next message it reads
+    pub fn sequence(&self, handle: &ConsumerHandle) -> usize {
+        self.consumers[handle.0].sequence.load(Ordering::Acquire)
+    }
+
+    /// Whether the consumer of the handle is still the one in its slot. Slots of dropped consumers
+    /// keep their final sequence until another consumer registers.
+    pub fn is_current(&self, handle: &ConsumerHandle) -> bool {
+        self.consumers[handle.0].generation.load(Ordering::Acquire) == handle.1
+    }
+
+    // lowest published sequence of the consumers in the bitmask
+    fn min_sequence(&self, mut consumers: u64) -> Option<usize> {
+        let mut min = None;
+        while consumers != 0 {
+            let slot = consumers.trailing_zeros() as usize;
+            consumers &= consumers - 1;
+            let s = self.consumers[slot].sequence.load(Ordering::Acquire);
+            min = Some(min.map_or(s, |m: usize| m.min(s)));
+        }
+        min
+    }
+
+    // Like `min_sequence`, but None if any of the consumers' slots was claimed by another consumer
+    // since the sum of their generations was `generation`
+    fn upstream_sequence(&self, mut consumers: u64, generation: u32) -> Option<usize> {
+        let mut min = usize::MAX;
+        let mut sum = 0u32;
+        while consumers != 0 {
+            let slot = consumers.trailing_zeros() as usize;
+            consumers &= consumers - 1;
+            let c = &self.consumers[slot];
+            // a new owner bumps the generation before publishing, so we see the bump if we see its sequence
+            min = min.min(c.sequence.load(Ordering::Acquire));
+            sum = sum.wrapping_add(c.generation.load(Ordering::Relaxed));
+        }
+        (sum == generation).then_some(min)
+    }
+}
+
+/// Identifies a registered consumer, can be handed to other threads or processes
+/// so their consumers can be set up to only read what this one already did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsumerHandle(usize, u32);
+
+/// How far a registered consumer is behind the producers, as of its last publish
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerLag {
+    pub handle:    ConsumerHandle,
+    pub name:      String,
+    pub pid:       u32,
+    pub sequence:  usize,
+    pub lag:       usize,
+    pub heartbeat: SystemTime,
 }
 
 #[cfg(feature = "shmem")]
@@ -78,48 +263,87 @@ fn power_of_two(mut v: usize) -> usize {
 }
 
 #[repr(C, align(64))]
-pub struct Queue<T> {
+/// `S` is the type of the slots, see `Slot`
+pub struct Queue<T, S = Seqlock<T>> {
     pub header: QueueHeader,
-    buffer:     [Seqlock<T>],
+    _t:         PhantomData<T>,
+    buffer:     [S],
 }
 
 impl<T: Copy> Queue<T> {
     /// Allocs (unshared) memory and initializes a new queue from it
     pub fn new(len: usize, queue_type: QueueType) -> Result<&'static Self, QueueError> {
+        Self::with_slots(len, queue_type)
+    }
+
+    /// Allocs a queue split in `n_shards` sub-rings of `len / n_shards` slots.
+    /// Each `Producer` claims its own shard, so producers never touch the same count,
+    /// and a `ShardedConsumer` round-robins over them.
+    pub fn new_sharded(len: usize, n_shards: usize) -> Result<&'static Self, QueueError> {
         let real_len = len.next_power_of_two();
-        let size =
-            std::mem::size_of::<QueueHeader>() + real_len * std::mem::size_of::<Seqlock<T>>();
+        Self::from_uninitialized_ptr_sharded(Self::alloc(real_len), real_len, n_shards)
+    }
+}
 
+impl<T: Copy, S: Slot<T>> Queue<T, S> {
+    /// Like `new` with slots of type `S`, e.g. `SmallSeqlock`
+    pub fn with_slots(len: usize, queue_type: QueueType) -> Result<&'static Self, QueueError> {
+        let real_len = len.next_power_of_two();
+        // Why real len you may ask. The size of the fat pointer ONLY includes the length of the
+        // unsized part of the struct i.e. the buffer.
+        Self::from_uninitialized_ptr(Self::alloc(real_len), real_len, queue_type)
+    }
+
+    fn alloc(len: usize) -> *mut u8 {
+        let size =
+            std::mem::size_of::<QueueHeader>() + len * std::mem::size_of::<S>();
         unsafe {
-            let ptr = std::alloc::alloc_zeroed(
+            std::alloc::alloc_zeroed(
                 Layout::array::<u8>(size)
                     .unwrap()
                     .align_to(64)
                     .unwrap()
                     .pad_to_align(),
-            );
-            // Why real len you may ask. The size of the fat pointer ONLY includes the length of the
-            // unsized part of the struct i.e. the buffer.
-            Self::from_uninitialized_ptr(ptr, real_len, queue_type)
+            )
         }
     }
 
     pub const fn size_of(len: usize) -> usize {
         size_of::<QueueHeader>()
-            + len.next_power_of_two() * size_of::<Seqlock<T>>()
+            + len.next_power_of_two() * size_of::<S>()
     }
 
     pub fn from_uninitialized_ptr(
         ptr: *mut u8,
         len: usize,
         queue_type: QueueType,
+    ) -> Result<&'static Self, QueueError> {
+        Self::init(ptr, len, queue_type, 1)
+    }
+
+    pub fn from_uninitialized_ptr_sharded(
+        ptr: *mut u8,
+        len: usize,
+        n_shards: usize,
+    ) -> Result<&'static Self, QueueError> {
+        if !n_shards.is_power_of_two() || n_shards > MAX_SHARDS || n_shards > len {
+            return Err(QueueError::InvalidShards);
+        }
+        Self::init(ptr, len, QueueType::Sharded, n_shards)
+    }
+
+    fn init(
+        ptr: *mut u8,
+        len: usize,
+        queue_type: QueueType,
+        n_shards: usize,
     ) -> Result<&'static Self, QueueError> {
         if !len.is_power_of_two() {
             return Err(QueueError::LengthNotPowerOfTwo);
         }
         unsafe {
-            let q = &mut *(std::ptr::slice_from_raw_parts_mut(ptr, len) as *mut Queue<T>);
-            let elsize = size_of::<Seqlock<T>>();
+            let q = &mut *(std::ptr::slice_from_raw_parts_mut(ptr, len) as *mut Queue<T, S>);
+            let elsize = size_of::<S>();
             if !len.is_power_of_two() {
                 return Err(QueueError::LengthNotPowerOfTwo);
             }
@@ -131,6 +355,21 @@ impl<T: Copy> Queue<T> {
             q.header.elsize = elsize as u32;
             q.header.is_initialized = true as u8;
             q.header.count = AtomicUsize::new(0);
+            q.header.futex = AtomicU32::new(0);
+            q.header.n_waiters = AtomicU32::new(0);
+            q.header.n_shards = n_shards as u16;
+            q.header.shards_claimed = AtomicU64::new(0);
+            q.header.consumers_claimed = AtomicU64::new(0);
+            q.header.read_count = PaddedCount::default();
+            for c in q.header.consumers.iter_mut() {
+                *c = ConsumerSlot::default();
+            }
+            for c in q.header.shard_counts.iter_mut() {
+                c.0 = AtomicUsize::new(0);
+            }
+            for g in q.header.groups.iter_mut() {
+                *g = GroupSlot::default();
+            }
             Ok(q)
         }
     }
@@ -146,7 +385,7 @@ impl<T: Copy> Queue<T> {
                 return Err(QueueError::UnInitialized);
             }
 
-            Ok(&*(std::ptr::slice_from_raw_parts_mut(ptr, len) as *const Queue<T>))
+            Ok(&*(std::ptr::slice_from_raw_parts_mut(ptr, len) as *const Queue<T, S>))
         }
     }
 
@@ -155,21 +394,24 @@ impl<T: Copy> Queue<T> {
         self.header.count.load(Ordering::Relaxed)
     }
 
-    fn next_count(&self) -> usize {
+    // reserves n contiguous counts, returning the first one
+    fn next_count(&self, n: usize) -> usize {
         match self.header.queue_type {
             QueueType::Unknown => panic!("Unknown queue"),
-            QueueType::MPMC => self.header.count.fetch_add(1, Ordering::AcqRel),
+            QueueType::Sharded => panic!("Sharded queue counts are per shard"),
+            QueueType::SPSC | QueueType::MPSC => panic!("Bounded queues reserve through try_next_count"),
+            QueueType::MPMC => self.header.count.fetch_add(n, Ordering::AcqRel),
             QueueType::SPMC => {
                 let c = self.header.count.load(Ordering::Relaxed);
                 self.header
                     .count
-                    .store(c.wrapping_add(1), Ordering::Relaxed);
+                    .store(c.wrapping_add(n), Ordering::Relaxed);
                 c
             }
         }
     }
 
-    fn load(&self, pos: usize) -> &Seqlock<T> {
+    fn load(&self, pos: usize) -> &S {
         unsafe { self.buffer.get_unchecked(pos) }
     }
 
@@ -177,28 +419,146 @@ impl<T: Copy> Queue<T> {
         ((self.count() / (self.header.mask + 1)) << 1) + 2
     }
 
+    fn is_bounded(&self) -> bool {
+        matches!(self.header.queue_type, QueueType::SPSC | QueueType::MPSC)
+    }
+
+    // reserves n contiguous counts if that doesn't overwrite unread messages of a bounded queue
+    fn try_next_count(&self, n: usize) -> Option<usize> {
+        let len = self.len();
+        let read = &self.header.read_count.0;
+        match self.header.queue_type {
+            QueueType::SPSC => {
+                let c = self.header.count.load(Ordering::Relaxed);
+                if c.wrapping_add(n).wrapping_sub(read.load(Ordering::Acquire)) > len {
+                    return None;
+                }
+                self.header
+                    .count
+                    .store(c.wrapping_add(n), Ordering::Relaxed);
+                Some(c)
+            }
+            QueueType::MPSC => self
+                .header
+                .count
+                .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |c| {
+                    (c.wrapping_add(n).wrapping_sub(read.load(Ordering::Acquire)) <= len)
+                        .then(|| c.wrapping_add(n))
+                })
+                .ok(),
+            _ => Some(self.next_count(n)),
+        }
+    }
+
+    // A slot of a bounded queue only gets reused after it was read, so its previous write is
+    // done and the plain single writer write is fine.
+    fn try_produce(&self, item: &T) -> Result<usize, QueueError> {
+        let p = self.try_next_count(1).ok_or(QueueError::Full)?;
+        self.write(p, item);
+        Ok(p)
+    }
+
+    // With multiple producers, two of them can hold counts c and c + len for the same slot.
+    // The count doubles as a ticket: the slot's version is the turn, so the lapping producer
+    // waits for the lapped one to finish instead of both writing at the same time.
+    fn write(&self, p: usize, item: &T) {
+        let lock = self.load(p & self.header.mask);
+        match self.header.queue_type {
+            QueueType::MPMC => lock.write_turn(item, (p / self.len()) << 1),
+            _ => lock.write(item),
+        }
+    }
+
     // returns the current count
     fn produce(&self, item: &T) -> usize {
-        let p = self.next_count();
-        let lock = self.load(p & self.header.mask);
-        lock.write(item);
+        let p = self.next_count(1);
+        self.write(p, item);
         p
     }
 
+    // returns the range of counts that were written
+    fn produce_batch(&self, items: &[T]) -> Range<usize> {
+        let p = if self.is_bounded() {
+            assert!(items.len() <= self.len(), "Batch larger than the queue");
+            let mut p = self.try_next_count(items.len());
+            while p.is_none() {
+                std::hint::spin_loop();
+                p = self.try_next_count(items.len());
+            }
+            p.unwrap()
+        } else {
+            self.next_count(items.len())
+        };
+        for (i, item) in items.iter().enumerate() {
+            self.write(p.wrapping_add(i), item);
+        }
+        p..p.wrapping_add(items.len())
+    }
+
+    fn shard_len(&self) -> usize {
+        self.len() / self.header.n_shards as usize
+    }
+
+    // only ever called by the single owner of the shard, so no need for an atomic increment
+    fn produce_shard(&self, shard: usize, item: &T) -> usize {
+        let count = &self.header.shard_counts[shard].0;
+        let c = count.load(Ordering::Relaxed);
+        count.store(c.wrapping_add(1), Ordering::Relaxed);
+        let shard_len = self.shard_len();
+        self.load(shard * shard_len + (c & (shard_len - 1))).write(item);
+        c
+    }
+
+    // Only parked consumers pay for the syscall, a fence and a relaxed load is all parking producers see.
+    // The fence pairs with the one in `park_until_ready`: either we see the waiter, or it sees our write.
+    fn notify(&self) {
+        fence(Ordering::SeqCst);
+        if self.header.n_waiters.load(Ordering::Relaxed) != 0 {
+            self.header.futex.fetch_add(1, Ordering::Release);
+            futex::wake_all(&self.header.futex);
+        }
+    }
+
     fn consume(&self, el: &mut T, ri: usize, ri_ver: usize) -> Result<(), ReadError> {
         self.load(ri).read_with_version(el, ri_ver)
     }
 
+    // Either a message is ready or we got sped past
+    fn is_ready(&self, ri: usize, ri_ver: usize) -> bool {
+        self.load(ri).version() >= ri_ver
+    }
+
     fn len(&self) -> usize {
         self.header.mask + 1
     }
 
+    /// Lag of every registered consumer, ordered by slot
+    pub fn consumer_lags(&self) -> Vec<ConsumerLag> {
+        let count = self.count();
+        let mut claimed = self.header.consumers_claimed.load(Ordering::Acquire);
+        let mut out = Vec::with_capacity(claimed.count_ones() as usize);
+        while claimed != 0 {
+            let slot = claimed.trailing_zeros() as usize;
+            claimed &= claimed - 1;
+            let c = &self.header.consumers[slot];
+            let sequence = c.sequence.load(Ordering::Acquire);
+            out.push(ConsumerLag {
+                handle: ConsumerHandle(slot, c.generation.load(Ordering::Acquire)),
+                name: c.name(),
+                pid: c.pid.load(Ordering::Relaxed),
+                sequence,
+                lag: count.saturating_sub(sequence),
+                heartbeat: UNIX_EPOCH + Duration::from_nanos(c.heartbeat.load(Ordering::Relaxed)),
+            });
+        }
+        out
+    }
 }
 
-unsafe impl<T> Send for Queue<T> {}
-unsafe impl<T> Sync for Queue<T> {}
+unsafe impl<T, S> Send for Queue<T, S> {}
+unsafe impl<T, S> Sync for Queue<T, S> {}
 
-impl<T: std::fmt::Debug> std::fmt::Debug for Queue<T> {
+impl<T: std::fmt::Debug, S> std::fmt::Debug for Queue<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Queue:\nHeader:\n{:?}", self.header)
     }
@@ -266,65 +626,508 @@ impl<T: Copy> Queue<T> {
 
 /// Simply exists for the automatic produce_first
 #[repr(C, align(64))]
-pub struct Producer<'a, T> {
-    pub queue:      &'a Queue<T>,
+pub struct Producer<'a, T, S = Seqlock<T>> {
+    pub queue:      &'a Queue<T, S>,
+    /// Only used for sharded queues
+    shard:          usize,
+    gated:          bool,
+    // wakes consumers sleeping in `park_until_ready` after every write
+    parking:        bool,
+    // counts below this don't overwrite anything the slowest registered consumer didn't read
+    gate:           usize,
 }
 
-impl<'a, T: Copy> From<&'a Queue<T>> for Producer<'a, T> {
-    fn from(queue: &'a Queue<T>) -> Self {
-        Self {
+impl<'a, T: Copy, S: Slot<T>> From<&'a Queue<T, S>> for Producer<'a, T, S> {
+    /// Panics if the queue is sharded and all shards are already claimed, see `Producer::new`
+    fn from(queue: &'a Queue<T, S>) -> Self {
+        Self::new(queue).expect("All shards claimed")
+    }
+}
+
+impl<'a, T: Copy, S: Slot<T>> Producer<'a, T, S> {
+    /// Errors with `QueueError::ShardsClaimed` if the queue is sharded and all shards are already claimed
+    pub fn new(queue: &'a Queue<T, S>) -> Result<Self, QueueError> {
+        let shard = match queue.header.queue_type {
+            QueueType::Sharded => queue.header.claim_shard().ok_or(QueueError::ShardsClaimed)?,
+            _ => 0,
+        };
+        Ok(Self {
             queue,
+            shard,
+            gated: false,
+            parking: false,
+            gate: 0,
+        })
+    }
+
+    /// Makes the producer of a SPMC or MPMC queue wait for the slowest registered consumer
+    /// instead of overwriting messages it didn't read yet
+    pub fn gated(mut self) -> Self {
+        assert!(
+            matches!(self.queue.header.queue_type, QueueType::SPMC | QueueType::MPMC),
+            "Only SPMC and MPMC producers can be gated"
+        );
+        self.gated = true;
+        self
+    }
+
+    /// Wakes consumers parked in `Consumer::park_until_ready` after every write.
+    /// Costs a full fence per produce, so only producers of queues with parked consumers should opt in.
+    pub fn parking(mut self) -> Self {
+        self.parking = true;
+        self
+    }
+
+    fn notify(&self) {
+        if self.parking {
+            self.queue.notify();
         }
     }
-}
 
-impl<'a, T: Copy> Producer<'a, T> {
+    // spins until counts p..p + n can be written without lapping a registered consumer
+    fn wait_for_gate(&mut self, p: usize, n: usize) {
+        let len = self.queue.len();
+        while p + n > self.gate {
+            let consumers = self.queue.header.consumers_claimed.load(Ordering::Acquire);
+            self.gate = self
+                .queue
+                .header
+                .min_sequence(consumers)
+                .map_or(p + n, |s| s + len);
+            std::hint::spin_loop();
+        }
+    }
+
+    fn produce_gated(&mut self, msgs: &[T]) -> Range<usize> {
+        let p = self.queue.next_count(msgs.len());
+        self.wait_for_gate(p, msgs.len());
+        for (i, msg) in msgs.iter().enumerate() {
+            self.queue.write(p.wrapping_add(i), msg);
+        }
+        p..p.wrapping_add(msgs.len())
+    }
+
+    /// Spins while a bounded queue is full or a gated producer waits for a consumer
     pub fn produce(&mut self, msg: &T) -> usize {
-        self.queue.produce(msg)
+        let p = match self.queue.header.queue_type {
+            QueueType::Sharded => self.queue.produce_shard(self.shard, msg),
+            QueueType::SPSC | QueueType::MPSC => return self.produce_with(msg, &BusySpin),
+            _ if self.gated => self.produce_gated(std::slice::from_ref(msg)).start,
+            _ => self.queue.produce(msg),
+        };
+        self.notify();
+        p
+    }
+
+    /// Errors with `QueueError::Full` if the consumer of a bounded queue didn't keep up.
+    /// Never fails on the other queue types.
+    pub fn try_produce(&mut self, msg: &T) -> Result<usize, QueueError> {
+        match self.queue.header.queue_type {
+            QueueType::SPSC | QueueType::MPSC => {
+                let p = self.queue.try_produce(msg)?;
+                self.notify();
+                Ok(p)
+            }
+            _ => Ok(self.produce(msg)),
+        }
+    }
+
+    /// Calls `wait` for as long as a bounded queue is full
+    pub fn produce_with(&mut self, msg: &T, wait: &impl WaitStrategy) -> usize {
+        let mut n = 0;
+        loop {
+            match self.try_produce(msg) {
+                Ok(p) => return p,
+                Err(_) => {
+                    wait.wait(n);
+                    n += 1;
+                }
+            }
+        }
+    }
+
+    /// Reserves all counts with a single `next_count` and returns them
+    pub fn produce_batch(&mut self, msgs: &[T]) -> Range<usize> {
+        let r = match self.queue.header.queue_type {
+            QueueType::Sharded => {
+                let start = self.queue.header.shard_counts[self.shard].0.load(Ordering::Relaxed);
+                for msg in msgs {
+                    self.queue.produce_shard(self.shard, msg);
+                }
+                start..start.wrapping_add(msgs.len())
+            }
+            _ if self.gated => self.produce_gated(msgs),
+            _ => self.queue.produce_batch(msgs),
+        };
+        self.notify();
+        r
     }
 }
 
-impl<'a, T> AsMut<Producer<'a, T>> for Producer<'a, T> {
-    fn as_mut(&mut self) -> &mut Producer<'a, T> {
+impl<'a, T, S> Drop for Producer<'a, T, S> {
+    fn drop(&mut self) {
+        if let QueueType::Sharded = self.queue.header.queue_type {
+            self.queue.header.release_shard(self.shard);
+        }
+    }
+}
+
+impl<'a, T, S> AsMut<Producer<'a, T, S>> for Producer<'a, T, S> {
+    fn as_mut(&mut self) -> &mut Producer<'a, T, S> {
         self
     }
 }
 
 #[repr(C)]
 #[derive(Debug)]
-pub struct Consumer<'a, T> {
+pub struct Consumer<'a, T, S = Seqlock<T>> {
     /// Shared reference to the channel
     /// Read index pointer
     pos:              usize, // 8
     mask:             usize,        // 16
     expected_version: usize,        // 24
-    queue:            &'a Queue<T>, // 48 fat ptr: (usize, pointer)
+    queue:            &'a Queue<T, S>, // 40 fat ptr: (usize, pointer)
+    // counts below this were read by all upstream consumers
+    limit:            usize,        // 48
+    // we publish our sequence when pos & publish_mask == 0
+    publish_mask:     u32,          // 52
+    // sum of the generations of the upstream consumers' handles
+    upstream_generation: u32,       // 56
+    // bitmask of the registered consumers we may not read past
+    upstream:         u16,          // 58
+    // cached so we don't have to touch the header, which shares a cache line with count
+    bounded:          bool,         // 59
+    slot:             Option<u8>,   // 61
 }
 
-impl<'a, T: Copy> Consumer<'a, T> {
+impl<'a, T: Copy, S: Slot<T>> Consumer<'a, T, S> {
     fn update_pos(&mut self) {
         self.pos = (self.pos + 1) & self.mask;
         self.expected_version += 2 * (self.pos == 0) as usize;
+        if self.bounded {
+            self.publish_read_count();
+        }
+        if self.pos as u32 & self.publish_mask == 0 {
+            self.publish_sequence();
+        }
+    }
+
+    /// Continues with the next message to be produced, e.g. to recover from `ReadError::SpedPast`
+    pub fn resync(&mut self) {
+        let c = self.queue.count();
+        self.pos = c & self.mask;
+        self.expected_version = ((c / (self.mask + 1)) << 1) + 2;
+        self.publish_count();
+    }
+
+    fn publish_read_count(&self) {
+        self.queue
+            .header
+            .read_count
+            .0
+            .store(self.read_count(), Ordering::Release);
+    }
+
+    fn publish_sequence(&self) {
+        if let Some(slot) = self.slot {
+            self.queue.header.consumers[slot as usize].publish(self.read_count());
+        }
+    }
+
+    fn publish_count(&self) {
+        if self.bounded {
+            self.publish_read_count();
+        }
+        self.publish_sequence();
+    }
+
+    // we caught up, so make sure whoever waits on us sees that
+    fn publish_if_behind(&self) {
+        if let Some(slot) = self.slot {
+            let c = &self.queue.header.consumers[slot as usize];
+            let read_count = self.read_count();
+            if c.sequence.load(Ordering::Relaxed) != read_count {
+                c.publish(read_count);
+            }
+        }
+    }
+
+    /// Publishes our position in the header so gated producers and downstream consumers
+    /// can follow it and `Queue::consumer_lags` reports it. Returns `None` if all `MAX_CONSUMERS` slots are taken.
+    pub fn register(&mut self) -> Option<ConsumerHandle> {
+        if self.slot.is_none() {
+            let slot = self.queue.header.claim_consumer()?;
+            let c = &self.queue.header.consumers[slot];
+            c.generation.fetch_add(1, Ordering::Release);
+            c.pid.store(std::process::id(), Ordering::Relaxed);
+            c.set_name("");
+            c.beat(unsafe { _rdtsc() });
+            self.slot = Some(slot as u8);
+            self.publish_sequence();
+        }
+        self.slot.map(|s| {
+            let generation = self.queue.header.consumers[s as usize].generation.load(Ordering::Relaxed);
+            ConsumerHandle(s as usize, generation)
+        })
+    }
+
+    /// Registers under a name to show up in `Queue::consumer_lags`, truncated to 32 bytes
+    pub fn named(mut self, name: &str) -> Self {
+        self.register();
+        if let Some(slot) = self.slot {
+            self.queue.header.consumers[slot as usize].set_name(name);
+        }
+        self
+    }
+
+    /// Publishes our sequence every `n` messages (rounded up to a power of two and capped at the
+    /// queue length) instead of every `DEFAULT_PUBLISH_EVERY`. Lower means gated producers and
+    /// downstream consumers follow more closely, higher means touching the header less often.
+    pub fn publish_every(mut self, n: usize) -> Self {
+        self.publish_mask = (n.max(1).next_power_of_two().min(self.mask + 1).min(1 << 31) - 1) as u32;
+        self
+    }
+
+    /// Never reads past any of the `upstream` consumers. Once the slot of one of them is taken over
+    /// by a new consumer we stop where we last saw them, call `after` again to follow the new one.
+    pub fn after(mut self, upstream: &[&ConsumerHandle]) -> Self {
+        self.upstream = 0;
+        self.upstream_generation = 0;
+        for h in upstream {
+            if self.upstream & 1 << h.0 == 0 {
+                self.upstream |= 1 << h.0;
+                self.upstream_generation = self.upstream_generation.wrapping_add(h.1);
+            }
+        }
+        self.limit = 0;
+        self
+    }
+
+    // counts below this were read by all upstream consumers, `limit` if one of them was replaced
+    fn upstream_limit(&self) -> usize {
+        self.queue
+            .header
+            .upstream_sequence(self.upstream as u64, self.upstream_generation)
+            .unwrap_or(self.limit)
+    }
+
+    // whether the upstream consumers already read the next message
+    fn upstream_done(&mut self) -> bool {
+        if self.upstream == 0 {
+            return true;
+        }
+        let c = self.read_count();
+        if c < self.limit {
+            return true;
+        }
+        self.limit = self.upstream_limit();
+        c < self.limit
     }
 
     /// Nonblocking consume returning either Ok(()) or a ReadError
     pub fn try_consume(&mut self, el: &mut T) -> Result<(), ReadError> {
-        self.queue.consume(el, self.pos, self.expected_version)?;
+        if !self.upstream_done() {
+            self.publish_if_behind();
+            return Err(ReadError::Empty);
+        }
+        if let Err(e) = self.queue.consume(el, self.pos, self.expected_version) {
+            if e == ReadError::Empty {
+                self.publish_if_behind();
+            }
+            return Err(e);
+        }
         self.update_pos();
         Ok(())
     }
 
+    /// Reads the next message without advancing
+    pub fn peek(&self, el: &mut T) -> Result<(), ReadError> {
+        let c = self.read_count();
+        if self.upstream != 0 && c >= self.limit && c >= self.upstream_limit() {
+            return Err(ReadError::Empty);
+        }
+        self.queue.consume(el, self.pos, self.expected_version)
+    }
+
+    /// Advances past the next `n` messages without reading them.
+    /// Consumers of bounded queues stop at the last produced message.
+    pub fn skip(&mut self, mut n: usize) {
+        if self.bounded {
+            // publishing a read count past the producers' would make the queue look full forever
+            n = n.min(self.queue.count().wrapping_sub(self.read_count()));
+        }
+        let p = self.pos + n;
+        self.pos = p & self.mask;
+        self.expected_version += 2 * (p / (self.mask + 1));
+        self.publish_count();
+    }
+
+    /// Iterates over the ready messages, ending at `ReadError::Empty`.
+    /// `ReadError::SpedPast` is yielded once, after which the iterator ends as well.
+    pub fn try_iter(&mut self) -> TryIter<'_, 'a, T, S> {
+        TryIter {
+            consumer: self,
+            done:     false,
+        }
+    }
+
+    /// Drains up to `els.len()` ready messages, returning how many were read.
+    /// Errors only if not a single message could be read.
+    /// Readiness is checked once for the whole run and our position is published once at the end.
+    pub fn consume_batch(&mut self, els: &mut [T]) -> Result<usize, ReadError> {
+        let len = self.mask + 1;
+        let c = self.read_count();
+        let mut n = els.len().min(len);
+        if self.upstream != 0 {
+            if c + n > self.limit {
+                self.limit = self.upstream_limit();
+            }
+            n = n.min(self.limit.saturating_sub(c));
+        }
+        // if the last message of the run isn't there yet, only go as far as the producers got
+        let last = c + n.max(1) - 1;
+        if !self.queue.is_ready(last & self.mask, ((last / len) << 1) + 2) {
+            n = n.min(self.queue.count().wrapping_sub(c));
+        }
+        if n == 0 {
+            self.publish_if_behind();
+            return Err(ReadError::Empty);
+        }
+        let (mut pos, mut version) = (self.pos, self.expected_version);
+        let mut read = 0;
+        for el in els[..n].iter_mut() {
+            if let Err(e) = self.queue.consume(el, pos, version) {
+                if read == 0 {
+                    if e == ReadError::Empty {
+                        self.publish_if_behind();
+                    }
+                    return Err(e);
+                }
+                break;
+            }
+            pos = (pos + 1) & self.mask;
+            version += 2 * (pos == 0) as usize;
+            read += 1;
+        }
+        self.pos = pos;
+        self.expected_version = version;
+        self.publish_count();
+        Ok(read)
+    }
+
+    /// Blocking consume, calling `wait` for as long as the queue is empty.
+    /// Only returns an error if we got sped past.
+    pub fn consume(&mut self, el: &mut T, wait: &impl WaitStrategy) -> Result<(), ReadError> {
+        let mut n = 0;
+        loop {
+            match self.try_consume(el) {
+                Err(ReadError::Empty) => {
+                    wait.wait(n);
+                    n += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+
+    /// Sleeps on the header's futex word until a message is ready or `timeout` passes,
+    /// returning whether one is ready. Works across processes when the queue lives in shared memory.
+    /// Only `Producer::parking` producers wake us up, writes of the others are noticed at `timeout`.
+    pub fn park_until_ready(&mut self, timeout: Duration) -> bool {
+        let header = &self.queue.header;
+        header.n_waiters.fetch_add(1, Ordering::SeqCst);
+        // pairs with the fence in `Queue::notify`
+        fence(Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        let ready = loop {
+            let val = header.futex.load(Ordering::Acquire);
+            if self.queue.is_ready(self.pos, self.expected_version) && self.upstream_done() {
+                break true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+            futex::wait(&header.futex, val, deadline - now);
+        };
+        header.n_waiters.fetch_sub(1, Ordering::Release);
+        ready
+    }
+
+    /// Like `consume` but gives up with `ReadError::Empty` once `timeout` has passed
+    pub fn consume_timeout(
+        &mut self,
+        el: &mut T,
+        wait: &impl WaitStrategy,
+        timeout: Duration,
+    ) -> Result<(), ReadError> {
+        let deadline = Instant::now() + timeout;
+        let mut n = 0;
+        loop {
+            match self.try_consume(el) {
+                Err(ReadError::Empty) => {
+                    if Instant::now() >= deadline {
+                        return Err(ReadError::Empty);
+                    }
+                    wait.wait(n);
+                    n += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+pub struct TryIter<'c, 'a, T, S = Seqlock<T>> {
+    consumer: &'c mut Consumer<'a, T, S>,
+    done:     bool,
 }
 
-impl<'a, T> AsMut<Consumer<'a, T>> for Consumer<'a, T> {
-    fn as_mut(&mut self) -> &mut Consumer<'a, T> {
+impl<'c, 'a, T: Copy, S: Slot<T>> Iterator for TryIter<'c, 'a, T, S> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut out = MaybeUninit::<T>::uninit();
+        // T: Copy so writing into the uninitialized memory never drops anything
+        match self.consumer.try_consume(unsafe { &mut *out.as_mut_ptr() }) {
+            Ok(()) => Some(Ok(unsafe { out.assume_init() })),
+            Err(ReadError::Empty) => None,
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a, T, S> AsMut<Consumer<'a, T, S>> for Consumer<'a, T, S> {
+    fn as_mut(&mut self) -> &mut Consumer<'a, T, S> {
         self
     }
 }
 
-impl<'a, T: Copy> From<&'a Queue<T>> for Consumer<'a, T> {
-    fn from(queue: &'a Queue<T>) -> Self {
-        let c = queue.header.count.load(Ordering::Relaxed);
+impl<'a, T: Copy, S: Slot<T>> From<&'a Queue<T, S>> for Consumer<'a, T, S> {
+    /// Consumers of bounded queues start at the first unread message, others at the next one
+    /// to be produced
+    fn from(queue: &'a Queue<T, S>) -> Self {
+        if queue.is_bounded() {
+            Self::at(queue, queue.header.read_count.0.load(Ordering::Acquire))
+        } else {
+            Self::at(queue, queue.header.count.load(Ordering::Relaxed))
+        }
+    }
+}
+
+impl<'a, T: Copy, S: Slot<T>> Consumer<'a, T, S> {
+    // starts reading at count c
+    fn at(queue: &'a Queue<T, S>, c: usize) -> Self {
+        assert!(
+            !matches!(queue.header.queue_type, QueueType::Sharded),
+            "Use a ShardedConsumer for sharded queues"
+        );
         let pos = c & queue.header.mask;
         let expected_version = ((c / queue.len()) << 1) + 2;
         Self {
@@ -332,13 +1135,197 @@ impl<'a, T: Copy> From<&'a Queue<T>> for Consumer<'a, T> {
             mask: queue.header.mask,
             expected_version,
             queue,
+            upstream: 0,
+            limit: 0,
+            upstream_generation: 0,
+            publish_mask: 0,
+            bounded: queue.is_bounded(),
+            slot: None,
+        }
+        .publish_every(DEFAULT_PUBLISH_EVERY)
+    }
+}
+
+impl<'a, T, S> Consumer<'a, T, S> {
+    // the count of the next message to be read
+    fn read_count(&self) -> usize {
+        ((self.expected_version - 2) >> 1) * (self.mask + 1) + self.pos
+    }
+}
+
+impl<'a, T, S> Drop for Consumer<'a, T, S> {
+    // publishes where we got, so downstream consumers can read up to there
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            self.queue.header.consumers[slot as usize].publish(self.read_count());
+            self.queue.header.release_consumer(slot as usize);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ShardPos {
+    pos:              usize,
+    expected_version: usize,
+}
+
+impl ShardPos {
+    // count c of the given shard
+    fn at(shard: usize, c: usize, shard_len: usize) -> Self {
+        Self {
+            pos:              shard * shard_len + (c & (shard_len - 1)),
+            expected_version: ((c / shard_len) << 1) + 2,
+        }
+    }
+}
+
+/// Merges all shards of a sharded queue by polling them round-robin.
+/// Messages of a single producer arrive in order, there is no order between producers.
+#[derive(Debug)]
+pub struct ShardedConsumer<'a, T, S = Seqlock<T>> {
+    shards:     Vec<ShardPos>,
+    shard_mask: usize,
+    next:       usize,
+    queue:      &'a Queue<T, S>,
+}
+
+impl<'a, T: Copy, S: Slot<T>> From<&'a Queue<T, S>> for ShardedConsumer<'a, T, S> {
+    fn from(queue: &'a Queue<T, S>) -> Self {
+        assert!(
+            matches!(queue.header.queue_type, QueueType::Sharded),
+            "ShardedConsumer needs a sharded queue"
+        );
+        let shard_len = queue.shard_len();
+        let shards = queue.header.shard_counts[..queue.header.n_shards as usize]
+            .iter()
+            .enumerate()
+            .map(|(i, c)| ShardPos::at(i, c.0.load(Ordering::Relaxed), shard_len))
+            .collect();
+        Self {
+            shards,
+            shard_mask: shard_len - 1,
+            next: 0,
+            queue,
+        }
+    }
+}
+
+impl<'a, T: Copy, S: Slot<T>> ShardedConsumer<'a, T, S> {
+    /// Nonblocking consume from the first shard with a message ready, starting after the shard
+    /// that was read last. Errors with `ReadError::SpedPast` if that happened on any shard,
+    /// which then continues with its oldest message that's still in the queue.
+    pub fn try_consume(&mut self, el: &mut T) -> Result<(), ReadError> {
+        let n = self.shards.len();
+        for i in 0..n {
+            let id = (self.next + i) & (n - 1);
+            let shard = &mut self.shards[id];
+            match self.queue.consume(el, shard.pos, shard.expected_version) {
+                Ok(()) => {
+                    let base = shard.pos & !self.shard_mask;
+                    let local = (shard.pos + 1) & self.shard_mask;
+                    shard.pos = base + local;
+                    shard.expected_version += 2 * (local == 0) as usize;
+                    self.next = id + 1;
+                    return Ok(());
+                }
+                Err(ReadError::SpedPast) => {
+                    let shard_len = self.shard_mask + 1;
+                    let c = self.queue.header.shard_counts[id].0.load(Ordering::Relaxed);
+                    *shard = ShardPos::at(id, c.saturating_sub(shard_len), shard_len);
+                    self.next = id + 1;
+                    return Err(ReadError::SpedPast);
+                }
+                Err(ReadError::Empty) => {}
+            }
+        }
+        Err(ReadError::Empty)
+    }
+}
+
+/// Shares the messages of a SPMC or MPMC queue between its members, each message is claimed by
+/// exactly one of them. The claim lives in the queue header, so members can join the same group
+/// id from other threads or processes, and come and go without losing or duplicating messages.
+pub struct ConsumerGroup<'a, T, S = Seqlock<T>> {
+    queue: &'a Queue<T, S>,
+    group: usize,
+}
+
+impl<'a, T: Copy, S: Slot<T>> ConsumerGroup<'a, T, S> {
+    pub fn join(queue: &'a Queue<T, S>, group: usize) -> Result<Self, QueueError> {
+        assert!(
+            matches!(queue.header.queue_type, QueueType::SPMC | QueueType::MPMC),
+            "Consumer groups need a SPMC or MPMC queue"
+        );
+        if group >= MAX_GROUPS {
+            return Err(QueueError::InvalidGroup);
+        }
+        let slot = &queue.header.groups[group];
+        slot.members.fetch_add(1, Ordering::AcqRel);
+        // the first member starts the group at the current count, like a new Consumer
+        let _ = slot
+            .claim
+            .compare_exchange(0, queue.count() + 1, Ordering::AcqRel, Ordering::Acquire);
+        Ok(Self { queue, group })
+    }
+
+    pub fn members(&self) -> usize {
+        self.queue.header.groups[self.group].members.load(Ordering::Acquire)
+    }
+
+    /// Claims the next message of the group, returning its count.
+    /// The message is read before it's claimed, so a member dying halfway never loses one.
+    /// On `SpedPast` the whole group moves on to the oldest message that's still in the queue.
+    pub fn try_claim(&self, el: &mut T) -> Result<usize, ReadError> {
+        let claim = &self.queue.header.groups[self.group].claim;
+        let len = self.queue.len();
+        loop {
+            let c = claim.load(Ordering::Acquire);
+            let p = c - 1;
+            match self.queue.consume(el, p & self.queue.header.mask, ((p / len) << 1) + 2) {
+                Ok(()) => {
+                    if claim
+                        .compare_exchange(c, c + 1, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        return Ok(p);
+                    }
+                }
+                Err(ReadError::SpedPast) => {
+                    let oldest = self.queue.count().saturating_sub(len);
+                    let _ = claim.compare_exchange(c, oldest + 1, Ordering::AcqRel, Ordering::Acquire);
+                    return Err(ReadError::SpedPast);
+                }
+                Err(ReadError::Empty) => return Err(ReadError::Empty),
+            }
+        }
+    }
+
+    /// Blocking claim, calling `wait` for as long as there's nothing to claim.
+    /// Only returns an error if the group got sped past.
+    pub fn claim(&self, el: &mut T, wait: &impl WaitStrategy) -> Result<usize, ReadError> {
+        let mut n = 0;
+        loop {
+            match self.try_claim(el) {
+                Err(ReadError::Empty) => {
+                    wait.wait(n);
+                    n += 1;
+                }
+                r => return r,
+            }
         }
     }
 }
 
+impl<'a, T, S> Drop for ConsumerGroup<'a, T, S> {
+    fn drop(&mut self) {
+        self.queue.header.groups[self.group].members.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::seqlock::ReadError;
+    use crate::wait::{Backoff, BusySpin, Pause, Sleep, Yield};
 
     use super::*;
 
@@ -349,8 +1336,14 @@ mod test {
     }
     #[test]
     fn headersize() {
-        assert_eq!(std::mem::size_of::<QueueHeader>(), 24);
-        assert_eq!(40, std::mem::size_of::<Consumer<'_, [u8; 60]>>())
+        assert_eq!(
+            std::mem::size_of::<QueueHeader>(),
+            128 + (MAX_SHARDS + MAX_CONSUMERS + MAX_GROUPS) * 64
+        );
+        // 40 bytes of position and queue, 24 of bounded, upstream and registration state,
+        // so the hot path still touches a single cache line
+        assert_eq!(64, std::mem::size_of::<Consumer<'_, [u8; 60]>>());
+        assert_eq!(64, std::mem::size_of::<ConsumerSlot>());
     }
 
     #[test]
@@ -383,6 +1376,157 @@ mod test {
         }
     }
 
+    fn blocking_test(wait: impl WaitStrategy + Send + 'static) {
+        let q = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut c = Consumer::from(q);
+        let mut m = 0;
+        assert_eq!(
+            c.consume_timeout(&mut m, &wait, Duration::from_millis(5)),
+            Err(ReadError::Empty)
+        );
+        let h = std::thread::spawn(move || {
+            let mut m = 0;
+            for i in 1..=10 {
+                c.consume(&mut m, &wait).unwrap();
+                assert_eq!(m, i);
+            }
+        });
+        let mut p = Producer::from(q);
+        for i in 1..=10 {
+            std::thread::sleep(Duration::from_millis(1));
+            p.produce(&i);
+        }
+        h.join().unwrap();
+    }
+
+    #[test]
+    fn blocking() {
+        blocking_test(BusySpin);
+        blocking_test(Pause);
+        blocking_test(Backoff::default());
+        blocking_test(Yield);
+        blocking_test(Sleep::default());
+    }
+
+    #[test]
+    fn batch() {
+        for typ in [QueueType::SPMC, QueueType::MPMC] {
+            let q = Queue::new(16, typ).unwrap();
+            let mut p = Producer::from(q);
+            let mut c = Consumer::from(q);
+            let mut out = [0; 8];
+            assert_eq!(c.consume_batch(&mut out), Err(ReadError::Empty));
+
+            assert_eq!(p.produce_batch(&[1, 2, 3]), 0..3);
+            assert_eq!(c.consume_batch(&mut out), Ok(3));
+            assert_eq!(out[..3], [1, 2, 3]);
+
+            let msgs: Vec<usize> = (0..12).collect();
+            assert_eq!(p.produce_batch(&msgs), 3..15);
+            assert_eq!(c.consume_batch(&mut out), Ok(8));
+            assert_eq!(out, [0, 1, 2, 3, 4, 5, 6, 7]);
+            assert_eq!(c.consume_batch(&mut out), Ok(4));
+            assert_eq!(out[..4], [8, 9, 10, 11]);
+
+            p.produce_batch(&[0; 20]);
+            assert_eq!(c.consume_batch(&mut out), Err(ReadError::SpedPast));
+        }
+
+        let q = Queue::new(8, QueueType::SPSC).unwrap();
+        let mut p = Producer::from(q);
+        let mut c = Consumer::from(q);
+        let mut out = [0; 8];
+        for i in 0..8 {
+            p.produce(&i);
+        }
+        assert!(matches!(p.try_produce(&8), Err(QueueError::Full)));
+        assert_eq!(c.consume_batch(&mut out[..5]), Ok(5));
+        // a single publish at the end frees all of the read slots
+        assert_eq!(q.header.read_count.0.load(Ordering::Relaxed), 5);
+        for i in 8..13 {
+            p.try_produce(&i).unwrap();
+        }
+        assert_eq!(c.consume_batch(&mut out), Ok(8));
+        assert_eq!(out, [5, 6, 7, 8, 9, 10, 11, 12]);
+
+        let q = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let mut a = Consumer::from(q).publish_every(1);
+        let h = a.register().unwrap();
+        let mut b = Consumer::from(q).after(&[&h]);
+        for i in 0..6 {
+            p.produce(&i);
+        }
+        let mut m = 0;
+        a.try_consume(&mut m).unwrap();
+        a.try_consume(&mut m).unwrap();
+        assert_eq!(b.consume_batch(&mut out), Ok(2));
+        assert_eq!(out[..2], [0, 1]);
+        assert_eq!(b.consume_batch(&mut out), Err(ReadError::Empty));
+    }
+
+    #[test]
+    fn peek_skip_iter() {
+        let q = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let mut c = Consumer::from(q);
+        let mut m = 0;
+        assert_eq!(c.peek(&mut m), Err(ReadError::Empty));
+        assert_eq!(c.try_iter().count(), 0);
+
+        for i in 0..10 {
+            p.produce(&i);
+        }
+        assert_eq!(c.peek(&mut m), Ok(()));
+        assert_eq!(m, 0);
+        assert_eq!(c.peek(&mut m), Ok(()));
+        assert_eq!(m, 0);
+        c.skip(3);
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m, 3);
+        let rest: Vec<usize> = c.try_iter().map(Result::unwrap).collect();
+        assert_eq!(rest, (4..10).collect::<Vec<_>>());
+
+        // skipping across the lap boundary
+        for i in 10..30 {
+            p.produce(&i);
+        }
+        c.skip(18);
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m, 28);
+        assert_eq!(c.try_iter().filter_map(Result::ok).sum::<usize>(), 29);
+
+        for i in 0..20 {
+            p.produce(&i);
+        }
+        let mut it = c.try_iter();
+        assert_eq!(it.next(), Some(Err(ReadError::SpedPast)));
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn park() {
+        let q = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut c = Consumer::from(q);
+        assert!(!c.park_until_ready(Duration::from_millis(5)));
+        let h = std::thread::spawn(move || {
+            let mut m = 0;
+            for i in 1..=10 {
+                let curt = Instant::now();
+                assert!(c.park_until_ready(Duration::from_secs(5)));
+                assert!(curt.elapsed() < Duration::from_secs(1));
+                c.try_consume(&mut m).unwrap();
+                assert_eq!(m, i);
+            }
+        });
+        let mut p = Producer::from(q).parking();
+        for i in 1..=10 {
+            std::thread::sleep(Duration::from_millis(5));
+            p.produce(&i);
+        }
+        h.join().unwrap();
+    }
+
     fn multithread(n_writers: usize, n_readers: usize, tot_messages: usize) {
         let q = Queue::new(16, QueueType::MPMC).unwrap();
 
@@ -423,6 +1567,470 @@ mod test {
             h.join();
         }
     }
+    // Many producers lapping each other on a tiny queue, the consumers check that no message
+    // is torn and that messages of each producer arrive in order
+    fn mpmc_contention(n_writers: usize, n_readers: usize, len: usize) {
+        const N_PER_WRITER: usize = 20000;
+        let q = Queue::<[usize; 8]>::new(len, QueueType::MPMC).unwrap();
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let mut readhandles = Vec::new();
+            for _ in 0..n_readers {
+                let done = &done;
+                let mut c = Consumer::from(q);
+                readhandles.push(s.spawn(move || {
+                    let mut last = vec![None; n_writers];
+                    let mut m = [0usize; 8];
+                    let mut n_read = 0;
+                    loop {
+                        match c.try_consume(&mut m) {
+                            Ok(()) => {
+                                let (writer, seq) = (m[0], m[1]);
+                                assert!(m[2..].iter().all(|&v| v == seq), "torn message {m:?}");
+                                if let Some(l) = last[writer] {
+                                    assert!(seq > l, "writer {writer}: {seq} after {l}");
+                                }
+                                last[writer] = Some(seq);
+                                n_read += 1;
+                            }
+                            Err(ReadError::SpedPast) => {
+                                // jump to the latest message rather than the next one,
+                                // otherwise a descheduled reader never catches anything on
+                                // such a tiny queue
+                                c = Consumer::at(q, q.count().saturating_sub(1))
+                            }
+                            Err(ReadError::Empty) => {
+                                if done.load(Ordering::Relaxed) {
+                                    break;
+                                }
+                                std::thread::yield_now();
+                            }
+                        }
+                    }
+                    n_read
+                }));
+            }
+            let writehandles: Vec<_> = (0..n_writers)
+                .map(|n| {
+                    s.spawn(move || {
+                        let mut p = Producer::from(q);
+                        let mut m = [n; 8];
+                        for seq in 0..N_PER_WRITER {
+                            m[1..].fill(seq);
+                            p.produce(&m);
+                            std::thread::yield_now();
+                        }
+                    })
+                })
+                .collect();
+            for h in writehandles {
+                h.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+            for h in readhandles {
+                assert!(h.join().unwrap() > 0);
+            }
+        });
+        assert_eq!(q.count(), n_writers * N_PER_WRITER);
+    }
+
+    #[test]
+    fn mpmc_contention_4_2() {
+        mpmc_contention(4, 2, 2);
+    }
+    #[test]
+    fn mpmc_contention_8_4() {
+        mpmc_contention(8, 4, 2);
+    }
+    #[test]
+    fn mpmc_contention_8_4_len_1() {
+        mpmc_contention(8, 4, 1);
+    }
+
+    #[test]
+    fn sharded() {
+        assert!(matches!(Queue::<usize>::new_sharded(16, 3), Err(QueueError::InvalidShards)));
+        assert!(matches!(Queue::<usize>::new_sharded(4, 8), Err(QueueError::InvalidShards)));
+
+        let q = Queue::new_sharded(16, 4).unwrap();
+        let mut producers: Vec<_> = (0..4).map(|_| Producer::from(q)).collect();
+        assert_eq!(q.header.claim_shard(), None);
+        assert!(matches!(Producer::new(q), Err(QueueError::ShardsClaimed)));
+        let mut c = ShardedConsumer::from(q);
+        let mut m = 0;
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+
+        for (i, p) in producers.iter_mut().enumerate() {
+            for j in 0..3 {
+                p.produce(&(10 * i + j));
+            }
+        }
+        let mut got = Vec::new();
+        while c.try_consume(&mut m).is_ok() {
+            got.push(m);
+        }
+        // round robin over the shards, in order within a shard
+        assert_eq!(got, [0, 10, 20, 30, 1, 11, 21, 31, 2, 12, 22, 32]);
+
+        // a dropped producer frees its shard, the new one continues where it left off
+        producers.remove(1);
+        let mut p = Producer::from(q);
+        assert_eq!(p.shard, 1);
+        assert_eq!(p.produce(&13), 3);
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m, 13);
+
+        for i in 0..5 {
+            p.produce(&i);
+        }
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::SpedPast));
+        // the lapped shard continues with its oldest message
+        let mut got = Vec::new();
+        while c.try_consume(&mut m).is_ok() {
+            got.push(m);
+        }
+        assert_eq!(got, [1, 2, 3, 4]);
+        p.produce(&5);
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m, 5);
+    }
+
+    #[test]
+    fn sharded_multithread() {
+        const N_PER_WRITER: usize = 20000;
+        let q = Queue::new_sharded(1024, 4).unwrap();
+        let mut c = ShardedConsumer::from(q);
+        std::thread::scope(|s| {
+            for n in 0..4 {
+                s.spawn(move || {
+                    let mut p = Producer::from(q);
+                    for i in 0..N_PER_WRITER {
+                        p.produce(&(n, i));
+                        if i % 128 == 0 {
+                            std::thread::sleep(Duration::from_micros(100));
+                        }
+                    }
+                });
+            }
+            let mut next = [0; 4];
+            let mut m = (0, 0);
+            while next.iter().any(|&n| n < N_PER_WRITER) {
+                match c.try_consume(&mut m) {
+                    Ok(()) => {
+                        assert_eq!(m.1, next[m.0]);
+                        next[m.0] += 1;
+                    }
+                    Err(e) => assert_eq!(e, ReadError::Empty),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn bounded() {
+        for typ in [QueueType::SPSC, QueueType::MPSC] {
+            let q = Queue::new(4, typ).unwrap();
+            let mut p = Producer::from(q);
+            let mut c = Consumer::from(q);
+            let mut m = 0;
+            assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+            for i in 0..4 {
+                assert_eq!(p.try_produce(&i).unwrap(), i);
+            }
+            assert!(matches!(p.try_produce(&4), Err(QueueError::Full)));
+            c.try_consume(&mut m).unwrap();
+            assert_eq!(m, 0);
+            assert_eq!(p.try_produce(&4).unwrap(), 4);
+            assert!(matches!(p.try_produce(&5), Err(QueueError::Full)));
+
+            // a new consumer picks up at the first unread message
+            drop(c);
+            let mut c = Consumer::from(q);
+            c.try_consume(&mut m).unwrap();
+            assert_eq!(m, 1);
+            c.skip(2);
+            assert_eq!(p.produce_batch(&[5, 6, 7]), 5..8);
+            let rest: Vec<usize> = c.try_iter().map(Result::unwrap).collect();
+            assert_eq!(rest, [4, 5, 6, 7]);
+
+            // skipping past the end only skips what was produced
+            assert_eq!(p.produce_batch(&[8, 9]), 8..10);
+            c.skip(5);
+            for i in 10..14 {
+                assert_eq!(p.try_produce(&i).unwrap(), i);
+            }
+            assert!(matches!(p.try_produce(&14), Err(QueueError::Full)));
+            let rest: Vec<usize> = c.try_iter().map(Result::unwrap).collect();
+            assert_eq!(rest, [10, 11, 12, 13]);
+        }
+    }
+
+    fn bounded_multithread(typ: QueueType, n_writers: usize) {
+        const N_PER_WRITER: usize = 20000;
+        let q = Queue::new(8, typ).unwrap();
+        let mut c = Consumer::from(q);
+        std::thread::scope(|s| {
+            for n in 0..n_writers {
+                s.spawn(move || {
+                    let mut p = Producer::from(q);
+                    for i in 0..N_PER_WRITER {
+                        p.produce_with(&(n, i), &Yield);
+                    }
+                });
+            }
+            // nothing can get lost, so every message of every writer arrives in order
+            let mut next = vec![0; n_writers];
+            let mut m = (0, 0);
+            for _ in 0..n_writers * N_PER_WRITER {
+                c.consume(&mut m, &Yield).unwrap();
+                assert_eq!(m.1, next[m.0]);
+                next[m.0] += 1;
+            }
+            assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+        });
+    }
+
+    #[test]
+    fn bounded_spsc() {
+        bounded_multithread(QueueType::SPSC, 1);
+    }
+    #[test]
+    fn bounded_mpsc() {
+        bounded_multithread(QueueType::MPSC, 4);
+    }
+
+    #[test]
+    fn gated() {
+        let q = Queue::new(4, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q).gated();
+        // only registered consumers hold back gated producers
+        let _idle = Consumer::from(q);
+        let mut a = Consumer::from(q).publish_every(1);
+        let ha = a.register().unwrap();
+        let mut b = Consumer::from(q).after(&[&ha]);
+        b.register().unwrap();
+        let mut m = 0;
+        for i in 0..4 {
+            p.produce(&i);
+        }
+        assert_eq!(b.try_consume(&mut m), Err(ReadError::Empty));
+        a.try_consume(&mut m).unwrap();
+        a.try_consume(&mut m).unwrap();
+        assert_eq!(q.header.sequence(&ha), 2);
+        assert_eq!(b.peek(&mut m), Ok(()));
+        assert_eq!(b.try_iter().map(Result::unwrap).collect::<Vec<_>>(), [0, 1]);
+
+        // the producer has to wait for both a and b before overwriting 2 and 3
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 4..8 {
+                    p.produce(&i);
+                }
+            });
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(a.try_iter().take(2).map(Result::unwrap).collect::<Vec<_>>(), [2, 3]);
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(b.try_iter().map(Result::unwrap).collect::<Vec<_>>(), [2, 3]);
+        });
+        assert_eq!(a.try_iter().map(Result::unwrap).collect::<Vec<_>>(), [4, 5, 6, 7]);
+
+        // slots are freed on drop
+        drop(a);
+        drop(b);
+        let mut others: Vec<_> = (0..MAX_CONSUMERS).map(|_| Consumer::from(q)).collect();
+        assert!(others.iter_mut().all(|c| c.register().is_some()));
+        assert_eq!(Consumer::from(q).register(), None);
+    }
+
+    #[test]
+    fn replaced_upstream() {
+        let q = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let mut a = Consumer::from(q);
+        let ha = a.register().unwrap();
+        let mut b = Consumer::from(q).after(&[&ha]);
+        for i in 0..8 {
+            p.produce(&i);
+        }
+        let mut m = 0;
+        for _ in 0..3 {
+            a.try_consume(&mut m).unwrap();
+        }
+        // dropping publishes how far a got
+        drop(a);
+        assert!(q.header.is_current(&ha));
+        assert_eq!(b.try_iter().map(Result::unwrap).collect::<Vec<_>>(), [0, 1, 2]);
+
+        // a new consumer in the same slot doesn't let b read any further
+        let mut c = Consumer::from(q);
+        let hc = c.register().unwrap();
+        assert_eq!(hc.0, ha.0);
+        assert!(!q.header.is_current(&ha));
+        assert_eq!(b.peek(&mut m), Err(ReadError::Empty));
+        assert_eq!(b.try_consume(&mut m), Err(ReadError::Empty));
+        let mut b = b.after(&[&hc]);
+        let peeker = &b;
+        assert_eq!(peeker.peek(&mut m), Ok(()));
+        assert_eq!(m, 3);
+        assert_eq!(b.try_iter().count(), 5);
+    }
+
+    #[test]
+    fn consumer_lags() {
+        let q = Queue::new(16, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let mut a = Consumer::from(q).named("a");
+        let mut b = Consumer::from(q).named("a name that is longer than 32 bytes").publish_every(4);
+        let ha = a.register().unwrap();
+        let hb = b.register().unwrap();
+        for i in 0..10 {
+            p.produce(&i);
+        }
+        let mut m = 0;
+        for _ in 0..10 {
+            a.try_consume(&mut m).unwrap();
+        }
+        for _ in 0..6 {
+            b.try_consume(&mut m).unwrap();
+        }
+        let lags = q.consumer_lags();
+        assert_eq!(lags.len(), 2);
+        assert_eq!((lags[0].handle, lags[0].name.as_str()), (ha, "a"));
+        assert_eq!(lags[0].pid, std::process::id());
+        assert!(lags[0].heartbeat > UNIX_EPOCH);
+        // a publishes every 16 messages by default, b every 4
+        assert_eq!((lags[0].sequence, lags[0].lag), (0, 10));
+        assert_eq!((lags[1].handle, lags[1].name.as_str()), (hb, "a name that is longer than 32 by"));
+        assert_eq!((lags[1].sequence, lags[1].lag), (4, 6));
+        // running out of messages publishes right away
+        assert_eq!(a.try_consume(&mut m), Err(ReadError::Empty));
+        assert_eq!(q.consumer_lags()[0].lag, 0);
+
+        drop(a);
+        assert_eq!(q.consumer_lags().len(), 1);
+        let mut c = Consumer::from(q);
+        c.register().unwrap();
+        assert_eq!(q.consumer_lags()[0].name, "");
+        drop(c);
+    }
+
+    #[test]
+    fn pipeline() {
+        const N: usize = 100000;
+        let q = Queue::new(16, QueueType::MPMC).unwrap();
+        let mut a = Consumer::from(q);
+        let ha = a.register().unwrap();
+        let mut b = Consumer::from(q).after(&[&ha]);
+        let hb = b.register().unwrap();
+        std::thread::scope(|s| {
+            for n in 0..2 {
+                s.spawn(move || {
+                    let mut p = Producer::from(q).gated();
+                    for i in (n..N).step_by(2) {
+                        p.produce(&i);
+                    }
+                });
+            }
+            s.spawn(move || {
+                let mut m = 0;
+                let mut sum = 0;
+                for _ in 0..N {
+                    a.consume(&mut m, &Yield).unwrap();
+                    sum += m;
+                }
+                assert_eq!(sum, (0..N).sum());
+            });
+            let mut m = 0;
+            let mut sum = 0;
+            for i in 0..N {
+                b.consume(&mut m, &Yield).unwrap();
+                assert!(q.header.sequence(&ha) > i);
+                sum += m;
+            }
+            assert_eq!(sum, (0..N).sum());
+            assert_eq!(q.header.sequence(&hb), N);
+        });
+    }
+
+    #[test]
+    fn group() {
+        let q = Queue::new(4, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let a = ConsumerGroup::join(q, 0).unwrap();
+        let b = ConsumerGroup::join(q, 0).unwrap();
+        let other = ConsumerGroup::join(q, 1).unwrap();
+        assert!(matches!(ConsumerGroup::join(q, MAX_GROUPS), Err(QueueError::InvalidGroup)));
+        assert_eq!(a.members(), 2);
+        let mut m = 0;
+        assert_eq!(a.try_claim(&mut m), Err(ReadError::Empty));
+        for i in 0..3 {
+            p.produce(&i);
+        }
+        assert_eq!(a.try_claim(&mut m), Ok(0));
+        assert_eq!(b.try_claim(&mut m), Ok(1));
+        assert_eq!(m, 1);
+        // groups are independent
+        assert_eq!(other.try_claim(&mut m), Ok(0));
+
+        // a member leaving doesn't lose anything
+        drop(b);
+        assert_eq!(a.members(), 1);
+        let c = ConsumerGroup::join(q, 0).unwrap();
+        assert_eq!(c.try_claim(&mut m), Ok(2));
+        assert_eq!(a.try_claim(&mut m), Err(ReadError::Empty));
+
+        for i in 3..10 {
+            p.produce(&i);
+        }
+        assert_eq!(a.try_claim(&mut m), Err(ReadError::SpedPast));
+        assert_eq!(c.try_claim(&mut m), Ok(6));
+        assert_eq!(m, 6);
+    }
+
+    #[test]
+    fn group_multithread() {
+        const N: usize = 100000;
+        let q = Queue::new(1024, QueueType::MPMC).unwrap();
+        let done = std::sync::atomic::AtomicBool::new(false);
+        let claimed = std::thread::scope(|s| {
+            let members: Vec<_> = (0..3)
+                .map(|_| {
+                    let m = ConsumerGroup::join(q, 0).unwrap();
+                    let done = &done;
+                    s.spawn(move || {
+                        let mut seen = Vec::new();
+                        let mut msg = 0;
+                        loop {
+                            match m.try_claim(&mut msg) {
+                                Ok(c) => {
+                                    assert_eq!(c, msg);
+                                    seen.push(msg);
+                                }
+                                Err(ReadError::Empty) if done.load(Ordering::Acquire) => break,
+                                Err(ReadError::Empty) => std::thread::yield_now(),
+                                Err(ReadError::SpedPast) => panic!("sped past"),
+                            }
+                        }
+                        seen
+                    })
+                })
+                .collect();
+            let mut p = Producer::from(q);
+            for i in 0..N {
+                // keep the producer from lapping the group on a busy machine
+                while q.header.groups[0].claim.load(Ordering::Acquire) + 512 < i {
+                    std::thread::yield_now();
+                }
+                p.produce(&i);
+            }
+            done.store(true, Ordering::Release);
+            members.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>()
+        });
+        let mut all: Vec<_> = claimed.into_iter().flatten().collect();
+        all.sort();
+        assert_eq!(all, (0..N).collect::<Vec<_>>());
+    }
+
     #[test]
     fn multithread_1_2() {
         multithread(1, 2, 100000);
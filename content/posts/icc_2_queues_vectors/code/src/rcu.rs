@@ -0,0 +1,200 @@
+use std::{
+    alloc::Layout,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicPtr, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+pub const MAX_READERS: usize = 64;
+
+#[derive(Default)]
+#[repr(align(64))]
+struct ReaderSlot(AtomicUsize);
+
+/// Alternative to `Seqlock` for large `T`: writers publish a freshly allocated copy by swapping a pointer,
+/// readers borrow the current one through an `RcuGuard` without copying and never retry.
+/// Replaced copies are freed once no guard taken before the swap is alive anymore.
+/// At most `MAX_READERS` guards can be alive at once, `load` spins until a slot frees up.
+pub struct RcuCell<T> {
+    ptr:     AtomicPtr<T>,
+    // starts at 1, 0 marks a free reader slot
+    epoch:   AtomicUsize,
+    readers: [ReaderSlot; MAX_READERS],
+    // replaced copies with the epoch they were replaced in
+    retired: Mutex<Vec<(*mut T, usize)>>,
+}
+unsafe impl<T: Send> Send for RcuCell<T> {}
+unsafe impl<T: Send + Sync> Sync for RcuCell<T> {}
+
+// allocates without going through the stack, `T` may be huge
+fn alloc_copy<T: Copy>(val: &T) -> *mut T {
+    unsafe {
+        let ptr = std::alloc::alloc(Layout::new::<T>()) as *mut T;
+        assert!(!ptr.is_null(), "Couldn't allocate {} bytes", std::mem::size_of::<T>());
+        std::ptr::copy_nonoverlapping(val, ptr, 1);
+        ptr
+    }
+}
+
+unsafe fn free<T>(ptr: *mut T) {
+    std::alloc::dealloc(ptr as *mut u8, Layout::new::<T>())
+}
+
+impl<T: Copy + Default> Default for RcuCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy> RcuCell<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            ptr:     AtomicPtr::new(alloc_copy(&data)),
+            epoch:   AtomicUsize::new(1),
+            readers: std::array::from_fn(|_| ReaderSlot::default()),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Number of writes so far
+    pub fn version(&self) -> usize {
+        self.epoch.load(Ordering::Acquire) - 1
+    }
+
+    pub fn load(&self) -> RcuGuard<'_, T> {
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            for (slot, r) in self.readers.iter().enumerate() {
+                if r.0.compare_exchange(0, epoch, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                    return RcuGuard {
+                        cell: self,
+                        slot,
+                        ptr: self.ptr.load(Ordering::SeqCst),
+                    };
+                }
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    pub fn read(&self, result: &mut T) {
+        let guard = self.load();
+        unsafe { std::ptr::copy_nonoverlapping(guard.ptr, result, 1) };
+    }
+
+    pub fn write(&self, val: &T) {
+        let new = alloc_copy(val);
+        let mut retired = self.retired.lock().unwrap();
+        let old = self.ptr.swap(new, Ordering::SeqCst);
+        // guards that saw an epoch up to this one may still point to `old`, later ones can't
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst);
+        retired.push((old, epoch));
+
+        let oldest = self
+            .readers
+            .iter()
+            .map(|r| r.0.load(Ordering::SeqCst))
+            .filter(|&e| e != 0)
+            .min()
+            .unwrap_or(usize::MAX);
+        retired.retain(|&(ptr, e)| {
+            if e < oldest {
+                unsafe { free(ptr) };
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl<T> Drop for RcuCell<T> {
+    fn drop(&mut self) {
+        unsafe {
+            free(*self.ptr.get_mut());
+            for &(ptr, _) in self.retired.get_mut().unwrap().iter() {
+                free(ptr);
+            }
+        }
+    }
+}
+
+pub struct RcuGuard<'a, T> {
+    cell: &'a RcuCell<T>,
+    slot: usize,
+    ptr:  *const T,
+}
+
+impl<T> Deref for RcuGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T> Drop for RcuGuard<'_, T> {
+    fn drop(&mut self) {
+        self.cell.readers[self.slot].0.store(0, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn guards() {
+        let cell = RcuCell::new([1usize; 4]);
+        let g1 = cell.load();
+        cell.write(&[2; 4]);
+        let g2 = cell.load();
+        cell.write(&[3; 4]);
+        // both replaced copies are still borrowed
+        assert_eq!(*g1, [1; 4]);
+        assert_eq!(*g2, [2; 4]);
+        assert_eq!(cell.retired.lock().unwrap().len(), 2);
+        drop(g1);
+        cell.write(&[4; 4]);
+        assert_eq!(cell.retired.lock().unwrap().len(), 2);
+        drop(g2);
+        cell.write(&[5; 4]);
+        assert_eq!(cell.retired.lock().unwrap().len(), 0);
+        let mut m = [0; 4];
+        cell.read(&mut m);
+        assert_eq!(m, [5; 4]);
+        assert_eq!(cell.version(), 4);
+    }
+
+    #[test]
+    fn rcu_multithread() {
+        const N: usize = 1024;
+        let cell = RcuCell::new([0usize; N]);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let g = cell.load();
+                        assert!(g.iter().all(|&i| i == g[0]));
+                    }
+                });
+            }
+            s.spawn(|| {
+                let curt = Instant::now();
+                let mut msg = [0usize; N];
+                while curt.elapsed() < Duration::from_millis(500) {
+                    msg.iter_mut().for_each(|i| *i += 1);
+                    cell.write(&msg);
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+        });
+    }
+}
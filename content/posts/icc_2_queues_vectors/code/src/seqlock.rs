@@ -1,5 +1,6 @@
 use std::arch::x86_64::_mm_pause;
 use std::cell::UnsafeCell;
+use std::hint::spin_loop;
 use std::slice::SliceIndex;
 use std::sync::atomic::{compiler_fence, fence, AtomicUsize, Ordering};
 use thiserror::Error;
@@ -12,14 +13,21 @@ pub enum ReadError {
     Empty,
 }
 
+/// Bytes copied by `Seqlock::read_chunked` in between checks of the version
+pub const READ_CHUNK: usize = 4096;
+
+// repr(C) so the data of a `Seqlock<[T]>` carved out of raw memory is where we expect it
 #[derive(Default)]
-#[repr(align(64))]
-pub struct Seqlock<T> {
+#[repr(C, align(64))]
+pub struct Seqlock<T: ?Sized> {
     version: AtomicUsize,
     data: UnsafeCell<T>,
 }
-unsafe impl<T: Send> Send for Seqlock<T> {}
-unsafe impl<T: Sync> Sync for Seqlock<T> {}
+unsafe impl<T: Send + ?Sized> Send for Seqlock<T> {}
+unsafe impl<T: Sync + ?Sized> Sync for Seqlock<T> {}
+
+/// A seqlock around a buffer whose length is only known at runtime
+pub type SeqlockSlice<T> = Seqlock<[T]>;
 
 impl<T: Copy> Seqlock<T> {
     pub fn new(data: T) -> Self {
@@ -28,6 +36,11 @@ impl<T: Copy> Seqlock<T> {
             data: UnsafeCell::new(data),
         }
     }
+
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
     #[inline(never)]
     pub fn read(&self, result: &mut T) {
         loop {
@@ -42,6 +55,47 @@ impl<T: Copy> Seqlock<T> {
         }
     }
 
+    /// For large `T`: copies `READ_CHUNK` bytes at a time and starts over as soon as the version
+    /// moved, rather than finding out after copying all of it like `read` does
+    #[inline(never)]
+    pub fn read_chunked(&self, result: &mut T) {
+        let size = std::mem::size_of::<T>();
+        let src = self.data.get() as *const u8;
+        let dst = result as *mut T as *mut u8;
+        'retry: loop {
+            let v1 = self.version.load(Ordering::Acquire);
+            if v1 & 1 == 1 {
+                spin_loop();
+                continue;
+            }
+            let mut off = 0;
+            while off < size {
+                let n = READ_CHUNK.min(size - off);
+                compiler_fence(Ordering::AcqRel);
+                unsafe { std::ptr::copy_nonoverlapping(src.add(off), dst.add(off), n) };
+                compiler_fence(Ordering::AcqRel);
+                if self.version.load(Ordering::Acquire) != v1 {
+                    continue 'retry;
+                }
+                off += n;
+            }
+            return;
+        }
+    }
+
+    /// A single attempt at `read`, false if the data changed underneath us
+    #[inline(never)]
+    pub fn try_read(&self, result: &mut T) -> bool {
+        let v1 = self.version.load(Ordering::Acquire);
+        if v1 & 1 == 1 {
+            return false;
+        }
+        compiler_fence(Ordering::AcqRel);
+        *result = unsafe { *self.data.get() };
+        compiler_fence(Ordering::AcqRel);
+        v1 == self.version.load(Ordering::Acquire)
+    }
+
     #[inline(never)]
     pub fn read_with_version(
         &self,
@@ -67,6 +121,30 @@ impl<T: Copy> Seqlock<T> {
             }
         }
     }
+
+    /// Only reads if the version moved on from `last_version`, which gets updated.
+    /// Returns whether `result` was written.
+    #[inline(never)]
+    pub fn read_if_changed(&self, result: &mut T, last_version: &mut usize) -> bool {
+        loop {
+            let v1 = self.version.load(Ordering::Acquire);
+            if v1 == *last_version {
+                return false;
+            }
+            if v1 & 1 == 1 {
+                continue;
+            }
+            compiler_fence(Ordering::AcqRel);
+            *result = unsafe { *self.data.get() };
+            compiler_fence(Ordering::AcqRel);
+            let v2 = self.version.load(Ordering::Acquire);
+            if v1 == v2 {
+                *last_version = v1;
+                return true;
+            }
+        }
+    }
+
     #[inline(never)]
     pub fn write(&self, val: &T) {
         let v = self.version.fetch_add(1, Ordering::Release);
@@ -75,6 +153,194 @@ impl<T: Copy> Seqlock<T> {
         compiler_fence(Ordering::AcqRel);
         self.version.store(v.wrapping_add(2), Ordering::Release);
     }
+
+    /// Single writer only: skips the write if `val` equals what's there already, so readers
+    /// don't lose their cache line to a no-op. Returns whether it wrote.
+    #[inline(never)]
+    pub fn write_if_changed(&self, val: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        // nobody else writes, so the data can't change underneath us
+        if unsafe { &*self.data.get() } == val {
+            return false;
+        }
+        self.write(val);
+        true
+    }
+
+    /// For multiple writers that were handed out unique turns: waits until the version reaches
+    /// `turn`, i.e. the writer of the previous turn is done, and leaves it at `turn + 2`.
+    #[inline(never)]
+    pub fn write_turn(&self, val: &T, turn: usize) {
+        let mut n = 0u32;
+        while self.version.load(Ordering::Acquire) != turn {
+            // spinning doesn't help if the previous writer got descheduled
+            n = n.wrapping_add(1);
+            if n & 1023 == 0 {
+                std::thread::yield_now();
+            } else {
+                spin_loop();
+            }
+        }
+        self.version.store(turn.wrapping_add(1), Ordering::Release);
+        compiler_fence(Ordering::AcqRel);
+        unsafe { *self.data.get() = *val };
+        compiler_fence(Ordering::AcqRel);
+        self.version.store(turn.wrapping_add(2), Ordering::Release);
+    }
+
+    /// For multiple writers that aren't coordinated otherwise: takes the lock by moving the
+    /// version from even to odd, so concurrent writes to the same lock are serialized.
+    #[inline(never)]
+    pub fn write_multi(&self, val: &T) {
+        let mut n = 0u32;
+        loop {
+            let v = self.version.load(Ordering::Relaxed);
+            if v & 1 == 0
+                && self
+                    .version
+                    .compare_exchange_weak(v, v.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                compiler_fence(Ordering::AcqRel);
+                unsafe { *self.data.get() = *val };
+                compiler_fence(Ordering::AcqRel);
+                self.version.store(v.wrapping_add(2), Ordering::Release);
+                return;
+            }
+            n = n.wrapping_add(1);
+            if n & 1023 == 0 {
+                std::thread::yield_now();
+            } else {
+                spin_loop();
+            }
+        }
+    }
+}
+
+/// What `SeqlockVector` and `Queue` keep their elements in. All zeroes has to be an unwritten slot
+/// at version 0, and every write bumps the version by 2, `write_turn` waiting until it's `turn`.
+pub trait Slot<T> {
+    fn version(&self) -> usize;
+    fn read(&self, result: &mut T);
+    fn read_with_version(&self, result: &mut T, expected_version: usize) -> Result<(), ReadError>;
+    fn read_if_changed(&self, result: &mut T, last_version: &mut usize) -> bool;
+    fn write(&self, val: &T);
+    fn write_turn(&self, val: &T, turn: usize);
+}
+
+impl<T: Copy> Slot<T> for Seqlock<T> {
+    fn version(&self) -> usize {
+        Seqlock::<T>::version(self)
+    }
+
+    fn read(&self, result: &mut T) {
+        Seqlock::read(self, result)
+    }
+
+    fn read_with_version(&self, result: &mut T, expected_version: usize) -> Result<(), ReadError> {
+        Seqlock::read_with_version(self, result, expected_version)
+    }
+
+    fn read_if_changed(&self, result: &mut T, last_version: &mut usize) -> bool {
+        Seqlock::read_if_changed(self, result, last_version)
+    }
+
+    fn write(&self, val: &T) {
+        Seqlock::write(self, val)
+    }
+
+    fn write_turn(&self, val: &T, turn: usize) {
+        Seqlock::write_turn(self, val, turn)
+    }
+}
+
+impl<T: Copy> Seqlock<[T]> {
+    pub fn len(&self) -> usize {
+        self.data.get().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Reads the first `out.len()` elements
+    #[inline(never)]
+    pub fn read_into(&self, out: &mut [T]) {
+        assert!(out.len() <= self.len(), "Reading {} elements out of {}", out.len(), self.len());
+        loop {
+            let v1 = self.version.load(Ordering::Acquire);
+            compiler_fence(Ordering::AcqRel);
+            unsafe {
+                std::ptr::copy_nonoverlapping(self.data.get() as *const T, out.as_mut_ptr(), out.len())
+            };
+            compiler_fence(Ordering::AcqRel);
+            let v2 = self.version.load(Ordering::Acquire);
+            if v1 == v2 && v1 & 1 == 0 {
+                return;
+            }
+        }
+    }
+
+    /// Writes the first `src.len()` elements
+    #[inline(never)]
+    pub fn write_from(&self, src: &[T]) {
+        assert!(src.len() <= self.len(), "Writing {} elements into {}", src.len(), self.len());
+        let v = self.version.fetch_add(1, Ordering::Release);
+        compiler_fence(Ordering::AcqRel);
+        unsafe { std::ptr::copy_nonoverlapping(src.as_ptr(), self.data.get() as *mut T, src.len()) };
+        compiler_fence(Ordering::AcqRel);
+        self.version.store(v.wrapping_add(2), Ordering::Release);
+    }
+}
+
+/// Two copies of the data, the writer always updates the one readers were not sent to and then
+/// points them at it. A read only fails if the writer finished a full write to the other copy
+/// and started on ours while we were reading, so a single writer that isn't faster than the
+/// readers costs them at most one retry.
+#[derive(Default)]
+#[repr(C, align(64))]
+pub struct DoubleSeqlock<T> {
+    // number of writes, readers go to copy `latch & 1`
+    latch:  AtomicUsize,
+    copies: [Seqlock<T>; 2],
+}
+
+impl<T: Copy> DoubleSeqlock<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            latch:  AtomicUsize::new(0),
+            copies: [Seqlock::new(data), Seqlock::new(data)],
+        }
+    }
+
+    pub fn version(&self) -> usize {
+        self.latch.load(Ordering::Acquire)
+    }
+
+    #[inline(never)]
+    pub fn try_read(&self, result: &mut T) -> bool {
+        let n = self.latch.load(Ordering::Acquire);
+        self.copies[n & 1].try_read(result)
+    }
+
+    #[inline(never)]
+    pub fn read(&self, result: &mut T) {
+        while !self.try_read(result) {}
+    }
+
+    /// Single writer only
+    #[inline(never)]
+    pub fn write(&self, val: &T) {
+        let n = self.latch.load(Ordering::Relaxed);
+        self.copies[(n + 1) & 1].write(val);
+        self.latch.store(n + 1, Ordering::Release);
+    }
 }
 
 #[cfg(test)]
@@ -86,13 +352,17 @@ mod tests {
     };
 
     fn read_test<const N: usize>() {
+        read_test_with::<N>(Seqlock::read)
+    }
+
+    fn read_test_with<const N: usize>(read: fn(&Seqlock<[usize; N]>, &mut [usize; N])) {
         let lock = Seqlock::new([0usize; N]);
         let done = AtomicBool::new(false);
         std::thread::scope(|s| {
             s.spawn(|| {
                 let mut msg = [0usize; N];
                 while !done.load(Ordering::Relaxed) {
-                    lock.read(&mut msg);
+                    read(&lock, &mut msg);
                     let first = msg[0];
                     for i in msg {
                         assert_eq!(first, i); // data consistency is verified here
@@ -114,6 +384,132 @@ mod tests {
         });
     }
 
+    #[test]
+    fn slice() {
+        let lock: Box<SeqlockSlice<usize>> = Box::new(Seqlock::new([0usize; 64]));
+        assert_eq!(lock.len(), 64);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut msg = vec![0usize; 64];
+                while !done.load(Ordering::Relaxed) {
+                    lock.read_into(&mut msg);
+                    assert!(msg.iter().all(|&i| i == msg[0]));
+                }
+            });
+            s.spawn(|| {
+                let curt = Instant::now();
+                let mut msg = vec![0usize; 64];
+                while curt.elapsed() < Duration::from_millis(200) {
+                    msg.iter_mut().for_each(|i| *i += 1);
+                    lock.write_from(&msg);
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+        });
+        let mut head = [0usize; 2];
+        lock.write_from(&[1, 2]);
+        lock.read_into(&mut head);
+        assert_eq!(head, [1, 2]);
+    }
+
+    #[test]
+    fn double() {
+        let lock = DoubleSeqlock::new([0usize; 64]);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut msg = [0usize; 64];
+                while !done.load(Ordering::Relaxed) {
+                    lock.read(&mut msg);
+                    assert!(msg.iter().all(|&i| i == msg[0]));
+                }
+            });
+            s.spawn(|| {
+                let curt = Instant::now();
+                let mut msg = [0usize; 64];
+                while curt.elapsed() < Duration::from_millis(200) {
+                    msg.iter_mut().for_each(|i| *i += 1);
+                    lock.write(&msg);
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+        });
+        // readers aren't disturbed by a write to the other copy
+        let lock = DoubleSeqlock::new(0usize);
+        lock.write(&1);
+        let mut m = 0;
+        let n = lock.latch.load(Ordering::Acquire);
+        lock.copies[(n + 1) & 1].version.fetch_add(1, Ordering::Release);
+        assert!(lock.try_read(&mut m));
+        assert_eq!((m, lock.version()), (1, 1));
+    }
+
+    #[test]
+    fn read_if_changed() {
+        let lock = Seqlock::new(0usize);
+        let mut m = 0;
+        let mut v = 0;
+        assert!(!lock.read_if_changed(&mut m, &mut v));
+        lock.write(&1);
+        assert!(lock.read_if_changed(&mut m, &mut v));
+        assert_eq!((m, v), (1, 2));
+        assert!(!lock.read_if_changed(&mut m, &mut v));
+        lock.write(&2);
+        lock.write(&3);
+        assert!(lock.read_if_changed(&mut m, &mut v));
+        assert_eq!((m, v), (3, 6));
+    }
+
+    #[test]
+    fn write_if_changed() {
+        let lock = Seqlock::new([1usize; 4]);
+        assert!(!lock.write_if_changed(&[1; 4]));
+        assert_eq!(lock.version(), 0);
+        assert!(lock.write_if_changed(&[2; 4]));
+        assert!(!lock.write_if_changed(&[2; 4]));
+        assert_eq!(lock.version(), 2);
+        let mut m = [0; 4];
+        lock.read(&mut m);
+        assert_eq!(m, [2; 4]);
+    }
+
+    #[test]
+    fn write_turn() {
+        let lock = Seqlock::new(0usize);
+        std::thread::scope(|s| {
+            // turns handed out in reverse, every writer has to wait for the previous one
+            for i in (0..8).rev() {
+                let lock = &lock;
+                s.spawn(move || lock.write_turn(&i, 2 * i));
+            }
+        });
+        let mut m = 0;
+        lock.read(&mut m);
+        assert_eq!(m, 7);
+        assert_eq!(lock.version(), 16);
+    }
+
+    #[test]
+    fn write_multi() {
+        const N: usize = 10000;
+        let lock = Seqlock::new([0usize; 4]);
+        std::thread::scope(|s| {
+            for i in 1..=3 {
+                let lock = &lock;
+                s.spawn(move || {
+                    for _ in 0..N {
+                        lock.write_multi(&[i; 4]);
+                    }
+                });
+            }
+        });
+        let mut m = [0; 4];
+        lock.read(&mut m);
+        assert!(m.iter().all(|&x| x == m[0]));
+        assert_eq!(lock.version(), 2 * 3 * N);
+    }
+
     #[test]
     fn read_16() {
         read_test::<16>()
@@ -134,4 +530,13 @@ mod tests {
     fn read_large() {
         read_test::<{ 2usize.pow(16) }>()
     }
+    #[test]
+    fn read_large_chunked() {
+        read_test_with::<{ 2usize.pow(16) }>(Seqlock::read_chunked)
+    }
+    #[test]
+    fn read_chunked_uneven() {
+        // last chunk shorter than READ_CHUNK
+        read_test_with::<{ READ_CHUNK / 8 * 3 + 5 }>(Seqlock::read_chunked)
+    }
 }
@@ -0,0 +1,273 @@
+use std::{arch::asm, cell::UnsafeCell, hint::spin_loop, marker::PhantomData, mem::size_of};
+
+use crate::seqlock::{ReadError, Slot};
+
+// Returns the previous value, `new` was only stored if that equals `old`.
+// rbx can't be an asm operand, so the low half of `new` is swapped in and out of it.
+#[inline(always)]
+unsafe fn cmpxchg16b(dst: *mut u128, old: u128, new: u128) -> u128 {
+    let (lo, hi): (u64, u64);
+    asm!(
+        "xchg {new_lo}, rbx",
+        "lock cmpxchg16b xmmword ptr [{dst}]",
+        "mov rbx, {new_lo}",
+        dst = in(reg) dst,
+        new_lo = inout(reg) new as u64 => _,
+        in("rcx") (new >> 64) as u64,
+        inout("rax") old as u64 => lo,
+        inout("rdx") (old >> 64) as u64 => hi,
+        options(nostack),
+    );
+    (hi as u128) << 64 | lo as u128
+}
+
+/// Seqlock for payloads of up to 8 bytes, e.g. a price and size pair of `f32`s.
+/// Version and payload share 16 bytes that are loaded and stored as one with `cmpxchg16b`,
+/// so a read is a single `cmpxchg16b` that never retries. A write loads the current value and
+/// retries its compare and swap for as long as other writers get in between, `write_turn` spins
+/// until its turn came.
+///
+/// `T` must not contain padding: the payload is copied as `size_of::<T>()` raw bytes, so padding
+/// would end up as uninitialized bytes in the 16 that get compared and swapped.
+///
+/// The price is that every load is a locked write as far as the cache is concerned: readers take
+/// the line exclusively, so they contend with each other as well as with the writer and get slower
+/// with every reader added, see the `small_latency` benches. Loads also can't be done on read only
+/// shared memory. As `Queue` slots four of them share a cache line, so consumers reading one slot
+/// steal the line from the producer writing the next. Stick to `Seqlock` for many readers.
+#[repr(C, align(16))]
+pub struct SmallSeqlock<T> {
+    // version in the low, payload in the high 8 bytes
+    data: UnsafeCell<u128>,
+    _t:   PhantomData<T>,
+}
+unsafe impl<T: Send> Send for SmallSeqlock<T> {}
+unsafe impl<T: Sync> Sync for SmallSeqlock<T> {}
+
+impl<T> Default for SmallSeqlock<T> {
+    fn default() -> Self {
+        Self {
+            data: UnsafeCell::new(0),
+            _t:   PhantomData,
+        }
+    }
+}
+
+impl<T: Copy> SmallSeqlock<T> {
+    const FITS: () = assert!(size_of::<T>() <= 8, "SmallSeqlock payloads can be at most 8 bytes");
+
+    pub fn new(data: T) -> Self {
+        Self {
+            data: UnsafeCell::new(Self::pack(0, &data)),
+            _t:   PhantomData,
+        }
+    }
+
+    // relies on `T` having no padding, see the type's docs
+    fn pack(version: usize, val: &T) -> u128 {
+        let () = Self::FITS;
+        let mut payload = 0u64;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                val as *const T as *const u8,
+                &mut payload as *mut u64 as *mut u8,
+                size_of::<T>(),
+            )
+        };
+        (payload as u128) << 64 | version as u128
+    }
+
+    fn unpack(data: u128, result: &mut T) {
+        let () = Self::FITS;
+        let payload = (data >> 64) as u64;
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                &payload as *const u64 as *const u8,
+                result as *mut T as *mut u8,
+                size_of::<T>(),
+            )
+        };
+    }
+
+    // a compare and swap of 0 with 0 only ever writes back what was there
+    fn load(&self) -> u128 {
+        unsafe { cmpxchg16b(self.data.get(), 0, 0) }
+    }
+
+    pub fn version(&self) -> usize {
+        self.load() as usize
+    }
+
+    #[inline(never)]
+    pub fn read(&self, result: &mut T) {
+        Self::unpack(self.load(), result)
+    }
+
+    #[inline(never)]
+    pub fn read_with_version(&self, result: &mut T, expected_version: usize) -> Result<(), ReadError> {
+        let data = self.load();
+        let v = data as usize;
+        if v != expected_version {
+            return Err(if v < expected_version { ReadError::Empty } else { ReadError::SpedPast });
+        }
+        Self::unpack(data, result);
+        Ok(())
+    }
+
+    #[inline(never)]
+    pub fn read_if_changed(&self, result: &mut T, last_version: &mut usize) -> bool {
+        let data = self.load();
+        if data as usize == *last_version {
+            return false;
+        }
+        *last_version = data as usize;
+        Self::unpack(data, result);
+        true
+    }
+
+    #[inline(never)]
+    pub fn write(&self, val: &T) {
+        let mut cur = self.load();
+        loop {
+            let prev = unsafe { cmpxchg16b(self.data.get(), cur, Self::pack((cur as usize).wrapping_add(2), val)) };
+            if prev == cur {
+                return;
+            }
+            cur = prev;
+        }
+    }
+
+    /// See `Seqlock::write_turn`
+    #[inline(never)]
+    pub fn write_turn(&self, val: &T, turn: usize) {
+        let new = Self::pack(turn.wrapping_add(2), val);
+        let mut cur = self.load();
+        let mut n = 0u32;
+        loop {
+            if cur as usize == turn {
+                let prev = unsafe { cmpxchg16b(self.data.get(), cur, new) };
+                if prev == cur {
+                    return;
+                }
+                cur = prev;
+                continue;
+            }
+            n = n.wrapping_add(1);
+            if n & 1023 == 0 {
+                std::thread::yield_now();
+            } else {
+                spin_loop();
+            }
+            cur = self.load();
+        }
+    }
+}
+
+impl<T: Copy> Slot<T> for SmallSeqlock<T> {
+    fn version(&self) -> usize {
+        SmallSeqlock::version(self)
+    }
+
+    fn read(&self, result: &mut T) {
+        SmallSeqlock::read(self, result)
+    }
+
+    fn read_with_version(&self, result: &mut T, expected_version: usize) -> Result<(), ReadError> {
+        SmallSeqlock::read_with_version(self, result, expected_version)
+    }
+
+    fn read_if_changed(&self, result: &mut T, last_version: &mut usize) -> bool {
+        SmallSeqlock::read_if_changed(self, result, last_version)
+    }
+
+    fn write(&self, val: &T) {
+        SmallSeqlock::write(self, val)
+    }
+
+    fn write_turn(&self, val: &T, turn: usize) {
+        SmallSeqlock::write_turn(self, val, turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Consumer, Producer, Queue, QueueType};
+    use crate::vector::SeqlockVector;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    struct Quote {
+        price: f32,
+        size:  u32,
+    }
+
+    #[test]
+    fn small() {
+        let lock = SmallSeqlock::new(Quote { price: 1.5, size: 3 });
+        assert_eq!(std::mem::size_of::<SmallSeqlock<Quote>>(), 16);
+        let mut q = Quote::default();
+        lock.read(&mut q);
+        assert_eq!(q, Quote { price: 1.5, size: 3 });
+        assert_eq!(lock.version(), 0);
+        assert_eq!(lock.read_with_version(&mut q, 2), Err(ReadError::Empty));
+        lock.write(&Quote { price: 2.0, size: 4 });
+        assert_eq!(lock.read_with_version(&mut q, 2), Ok(()));
+        assert_eq!(q, Quote { price: 2.0, size: 4 });
+        let mut v = 0;
+        assert!(lock.read_if_changed(&mut q, &mut v));
+        assert!(!lock.read_if_changed(&mut q, &mut v));
+        lock.write_turn(&Quote { price: 3.0, size: 5 }, 2);
+        assert_eq!(lock.read_with_version(&mut q, 2), Err(ReadError::SpedPast));
+        assert_eq!(lock.version(), 4);
+    }
+
+    #[test]
+    fn small_multithread() {
+        let lock = SmallSeqlock::new((0u32, 0u32));
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut m = (0, 0);
+                let mut last_version = 0;
+                while !done.load(Ordering::Relaxed) {
+                    if lock.read_if_changed(&mut m, &mut last_version) {
+                        assert_eq!(m.0, m.1);
+                        assert_eq!(last_version, 2 * m.0 as usize);
+                    }
+                }
+            });
+            s.spawn(|| {
+                for i in 1..=200000u32 {
+                    lock.write(&(i, i));
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+        });
+    }
+
+    #[test]
+    fn slot() {
+        let v = SeqlockVector::<Quote, SmallSeqlock<Quote>>::with_slots(4);
+        v.write(2, &Quote { price: 1.0, size: 1 });
+        assert_eq!(v.read_copy(2), Quote { price: 1.0, size: 1 });
+        assert_eq!(v.read_copy(1), Quote::default());
+
+        for typ in [QueueType::SPMC, QueueType::MPMC] {
+            let q = Queue::<u64, SmallSeqlock<u64>>::with_slots(8, typ).unwrap();
+            let mut p = Producer::from(q);
+            let mut c = Consumer::from(q);
+            let mut m = 0;
+            for i in 0..20 {
+                p.produce(&i);
+                c.try_consume(&mut m).unwrap();
+                assert_eq!(m, i);
+            }
+            assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+            for i in 0..9 {
+                p.produce(&i);
+            }
+            assert_eq!(c.try_consume(&mut m), Err(ReadError::SpedPast));
+        }
+    }
+}
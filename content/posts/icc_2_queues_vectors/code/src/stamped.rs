@@ -0,0 +1,91 @@
+use ma_time::Instant;
+use ma_timing::Timer;
+
+use crate::queue::{Consumer, Producer};
+use crate::seqlock::ReadError;
+use crate::wait::WaitStrategy;
+
+/// Message with the time it was published at, see `Producer::produce_stamped`
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Stamped<T> {
+    pub publish: Instant,
+    pub data:    T,
+}
+
+impl<'a, T: Copy> Producer<'a, Stamped<T>> {
+    /// Stamps `data` with the current time right before producing it
+    pub fn produce_stamped(&mut self, data: &T) -> usize {
+        self.produce(&Stamped {
+            publish: Instant::now(),
+            data:    *data,
+        })
+    }
+}
+
+impl<'a, T: Copy> Consumer<'a, Stamped<T>> {
+    /// Records the publish to consume latency of every message in a `Timer` called `name`
+    pub fn timed(self, name: &str) -> TimedConsumer<'a, T> {
+        TimedConsumer {
+            consumer: self,
+            timer:    Timer::new(name),
+        }
+    }
+}
+
+pub struct TimedConsumer<'a, T> {
+    consumer: Consumer<'a, Stamped<T>>,
+    timer:    Timer,
+}
+
+impl<'a, T: Copy> TimedConsumer<'a, T> {
+    pub fn try_consume(&mut self, el: &mut Stamped<T>) -> Result<(), ReadError> {
+        self.timer.start();
+        self.consumer.try_consume(el)?;
+        self.timer.stop_and_latency(el.publish);
+        Ok(())
+    }
+
+    /// Blocking consume, calling `wait` for as long as the queue is empty.
+    /// Only returns an error if we got sped past.
+    pub fn consume(&mut self, el: &mut Stamped<T>, wait: &impl WaitStrategy) -> Result<(), ReadError> {
+        let mut n = 0;
+        loop {
+            match self.try_consume(el) {
+                Err(ReadError::Empty) => {
+                    wait.wait(n);
+                    n += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> Consumer<'a, Stamped<T>> {
+        self.consumer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Queue, QueueType};
+
+    #[test]
+    fn stamped() {
+        let q = Queue::<Stamped<usize>>::new(16, QueueType::SPMC).unwrap();
+        let mut p = Producer::from(q);
+        let mut c = Consumer::from(q).timed("stamped_test");
+        let mut m = Stamped::default();
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+        p.produce_stamped(&1);
+        p.produce_stamped(&2);
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m.data, 1);
+        assert!(m.publish != Instant::default());
+        let first = m.publish;
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m.data, 2);
+        assert!(m.publish != first);
+    }
+}
@@ -0,0 +1,168 @@
+use ma_time::Instant;
+
+use crate::queue::Producer;
+
+/// Message that collects an `Instant` at every stage that publishes it, up to `N` of them.
+/// Stamps past the first `N` are dropped.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Traced<T, const N: usize> {
+    pub id:   u64,
+    n_stamps: usize,
+    stamps:   [Instant; N],
+    pub data: T,
+}
+
+impl<T: Copy, const N: usize> Traced<T, N> {
+    pub fn new(id: u64, data: T) -> Self {
+        Self {
+            id,
+            n_stamps: 0,
+            stamps: [Instant::default(); N],
+            data,
+        }
+    }
+
+    pub fn stamp(&mut self) {
+        if self.n_stamps < N {
+            self.stamps[self.n_stamps] = Instant::now();
+            self.n_stamps += 1;
+        }
+    }
+
+    pub fn stamps(&self) -> &[Instant] {
+        &self.stamps[..self.n_stamps]
+    }
+}
+
+impl<'a, T: Copy, const N: usize> Producer<'a, Traced<T, N>> {
+    /// Stamps a copy of `msg` right before producing it, so both the first producer and
+    /// every stage republishing a message it consumed call this
+    pub fn produce_traced(&mut self, msg: &Traced<T, N>) -> usize {
+        let mut m = *msg;
+        m.stamp();
+        self.produce(&m)
+    }
+}
+
+/// In nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopStats {
+    pub count: u64,
+    pub sum:   u64,
+    pub min:   u64,
+    pub max:   u64,
+}
+
+impl Default for HopStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum:   0,
+            min:   u64::MAX,
+            max:   0,
+        }
+    }
+}
+
+impl HopStats {
+    fn add(&mut self, from: Instant, to: Instant) {
+        let nanos = (to - from).0;
+        self.count += 1;
+        self.sum += nanos;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    /// `None` until something was recorded
+    pub fn mean(&self) -> Option<f64> {
+        (self.count != 0).then(|| self.sum as f64 / self.count as f64)
+    }
+}
+
+/// Sits at the end of a pipeline and breaks the latency of traced messages down per hop.
+/// Hop `i` goes from the `i`th stamp to the next one, the last hop ends when the message is recorded.
+#[derive(Debug)]
+pub struct TraceCollector<const N: usize> {
+    hops:  [HopStats; N],
+    total: HopStats,
+}
+
+impl<const N: usize> Default for TraceCollector<N> {
+    fn default() -> Self {
+        Self {
+            hops:  [HopStats::default(); N],
+            total: HopStats::default(),
+        }
+    }
+}
+
+impl<const N: usize> TraceCollector<N> {
+    pub fn record<T: Copy>(&mut self, msg: &Traced<T, N>) {
+        let now = Instant::now();
+        let stamps = msg.stamps();
+        let Some(&first) = stamps.first() else {
+            return;
+        };
+        for (i, hop) in stamps.windows(2).enumerate() {
+            self.hops[i].add(hop[0], hop[1]);
+        }
+        self.hops[stamps.len() - 1].add(stamps[stamps.len() - 1], now);
+        self.total.add(first, now);
+    }
+
+    /// Up to the last hop that was recorded at least once
+    pub fn hops(&self) -> &[HopStats] {
+        let n = self.hops.iter().rposition(|h| h.count != 0).map_or(0, |i| i + 1);
+        &self.hops[..n]
+    }
+
+    pub fn total(&self) -> HopStats {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{Consumer, Queue, QueueType};
+
+    #[test]
+    fn pipeline() {
+        type Msg = Traced<usize, 4>;
+        let q1 = Queue::<Msg>::new(16, QueueType::SPMC).unwrap();
+        let q2 = Queue::<Msg>::new(16, QueueType::SPMC).unwrap();
+        let mut p1 = Producer::from(q1);
+        let mut c1 = Consumer::from(q1);
+        let mut p2 = Producer::from(q2);
+        let mut c2 = Consumer::from(q2);
+        let mut collector = TraceCollector::default();
+        assert_eq!(collector.total().mean(), None);
+        let mut m = Msg::new(0, 0);
+        for i in 0..10 {
+            p1.produce_traced(&Msg::new(i, i as usize));
+            c1.try_consume(&mut m).unwrap();
+            m.data *= 2;
+            p2.produce_traced(&m);
+            c2.try_consume(&mut m).unwrap();
+            assert_eq!((m.id, m.data), (i, 2 * i as usize));
+            assert_eq!(m.stamps().len(), 2);
+            collector.record(&m);
+        }
+        let hops = collector.hops();
+        assert_eq!(hops.len(), 2);
+        assert!(hops.iter().all(|h| h.count == 10 && h.min <= h.max));
+        let total = collector.total();
+        assert_eq!(total.count, 10);
+        // up to a ns of rounding per hop
+        assert!(total.sum.abs_diff(hops[0].sum + hops[1].sum) <= 2 * total.count);
+        assert!(total.mean().is_some());
+
+        // stamps beyond N are dropped
+        let mut m = Traced::<(), 2>::new(0, ());
+        for _ in 0..3 {
+            m.stamp();
+        }
+        assert_eq!(m.stamps().len(), 2);
+    }
+}
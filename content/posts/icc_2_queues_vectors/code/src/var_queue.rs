@@ -0,0 +1,388 @@
+use std::{
+    alloc::Layout,
+    cell::UnsafeCell,
+    mem::size_of,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+use crate::queue::QueueError;
+use crate::seqlock::ReadError;
+
+const RECORD_HEADER: usize = 16;
+
+// records are 8 byte aligned, their headers and bytes can still wrap around the end of the buffer
+const fn record_size(len: usize) -> usize {
+    (RECORD_HEADER + len + 7) & !7
+}
+
+#[derive(Debug)]
+#[repr(C, align(64))]
+pub struct VarQueueHeader {
+    pub is_initialized: u8,
+    mask:               usize,
+    // Byte positions only ever grow. The producer bumps `tail_intent` before it touches
+    // the buffer and `tail` once the record is complete, like the odd and even versions of a seqlock.
+    tail_intent:        AtomicUsize,
+    tail:               AtomicUsize,
+    count:              AtomicUsize,
+}
+
+/// Single producer, multi consumer ring of variable length byte records.
+/// Only one `VarProducer` may write at a time, nothing stops two of them from racing on `tail_intent`.
+/// Every record is a 16 byte header holding its length and sequence number followed by the bytes,
+/// and may wrap around the end of the buffer. Consumers copy a record out and only then check
+/// whether the producer started overwriting it in the meantime, which makes it `SpedPast`.
+#[repr(C, align(64))]
+pub struct VarQueue {
+    header: VarQueueHeader,
+    buffer: [UnsafeCell<u8>],
+}
+unsafe impl Send for VarQueue {}
+unsafe impl Sync for VarQueue {}
+
+impl VarQueue {
+    /// `size` in bytes, rounded up to a power of two
+    pub fn new(size: usize) -> Result<&'static Self, QueueError> {
+        let size = size.next_power_of_two();
+        let ptr = unsafe {
+            std::alloc::alloc_zeroed(
+                Layout::array::<u8>(Self::size_of(size))
+                    .unwrap()
+                    .align_to(64)
+                    .unwrap()
+                    .pad_to_align(),
+            )
+        };
+        Self::from_uninitialized_ptr(ptr, size)
+    }
+
+    pub const fn size_of(size: usize) -> usize {
+        size_of::<VarQueueHeader>() + size.next_power_of_two()
+    }
+
+    pub fn from_uninitialized_ptr(ptr: *mut u8, size: usize) -> Result<&'static Self, QueueError> {
+        if !size.is_power_of_two() {
+            return Err(QueueError::LengthNotPowerOfTwo);
+        }
+        if size < RECORD_HEADER {
+            return Err(QueueError::SizeTooSmall);
+        }
+        unsafe {
+            let q = &mut *(std::ptr::slice_from_raw_parts_mut(ptr, size) as *mut VarQueue);
+            q.header.mask = size - 1;
+            q.header.tail_intent = AtomicUsize::new(0);
+            q.header.tail = AtomicUsize::new(0);
+            q.header.count = AtomicUsize::new(0);
+            q.header.is_initialized = true as u8;
+            Ok(q)
+        }
+    }
+
+    /// Attaches to a queue that was set up already, e.g. by another process through shared memory
+    ///
+    /// # Safety
+    /// `ptr` has to point to a `VarQueueHeader` followed by its buffer, valid for the rest of the program.
+    pub unsafe fn from_initialized_ptr(ptr: *mut VarQueueHeader) -> Result<&'static Self, QueueError> {
+        unsafe {
+            let size = (*ptr).mask.wrapping_add(1);
+            if !size.is_power_of_two() {
+                return Err(QueueError::LengthNotPowerOfTwo);
+            }
+            if (*ptr).is_initialized != true as u8 {
+                return Err(QueueError::UnInitialized);
+            }
+            Ok(&*(std::ptr::slice_from_raw_parts_mut(ptr as *mut u8, size) as *const VarQueue))
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.header.mask + 1
+    }
+
+    /// Number of records produced so far
+    pub fn count(&self) -> usize {
+        self.header.count.load(Ordering::Relaxed)
+    }
+
+    fn ptr(&self, pos: usize) -> *mut u8 {
+        UnsafeCell::raw_get(unsafe { self.buffer.as_ptr().add(pos & self.header.mask) })
+    }
+
+    // copies `src` to `pos`, wrapping around the end of the buffer
+    fn copy_in(&self, pos: usize, src: &[u8]) {
+        let start = pos & self.header.mask;
+        let first = src.len().min(self.capacity() - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(src.as_ptr(), self.ptr(start), first);
+            std::ptr::copy_nonoverlapping(src.as_ptr().add(first), self.ptr(0), src.len() - first);
+        }
+    }
+
+    fn copy_out(&self, pos: usize, dst: &mut [u8]) {
+        let start = pos & self.header.mask;
+        let first = dst.len().min(self.capacity() - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.ptr(start), dst.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(self.ptr(0), dst.as_mut_ptr().add(first), dst.len() - first);
+        }
+    }
+}
+
+#[cfg(feature = "shmem")]
+impl VarQueue {
+    pub fn shared<P: AsRef<std::path::Path>>(shmem_flink: P, size: usize) -> Result<&'static Self, QueueError> {
+        use shared_memory::{ShmemConf, ShmemError};
+        let size = size.next_power_of_two();
+        match ShmemConf::new()
+            .size(Self::size_of(size))
+            .flink(&shmem_flink)
+            .create()
+        {
+            Ok(shmem) => {
+                let ptr = shmem.as_ptr();
+                std::mem::forget(shmem);
+                Self::from_uninitialized_ptr(ptr, size)
+            }
+            Err(ShmemError::LinkExists) => Self::open_shared(shmem_flink),
+            Err(e) => {
+                eprintln!(
+                    "Unable to create or open shmem flink {:?} : {e}",
+                    shmem_flink.as_ref()
+                );
+                Err(e.into())
+            }
+        }
+    }
+
+    pub fn open_shared<P: AsRef<std::path::Path>>(shmem_flink: P) -> Result<&'static Self, QueueError> {
+        use shared_memory::ShmemConf;
+        match ShmemConf::new().flink(&shmem_flink).open() {
+            Ok(shmem) => {
+                let ptr = shmem.as_ptr() as *mut VarQueueHeader;
+                std::mem::forget(shmem);
+                unsafe { Self::from_initialized_ptr(ptr) }
+            }
+            Err(e) => {
+                eprintln!(
+                    "Unable to create or open shmem flink {:?} : {e}",
+                    shmem_flink.as_ref()
+                );
+                Err(e.into())
+            }
+        }
+    }
+}
+
+pub struct VarProducer<'a> {
+    queue: &'a VarQueue,
+}
+
+impl<'a> From<&'a VarQueue> for VarProducer<'a> {
+    fn from(queue: &'a VarQueue) -> Self {
+        Self { queue }
+    }
+}
+
+impl<'a> VarProducer<'a> {
+    /// Returns the sequence number of the record. Records that take up more than
+    /// the whole buffer error with `QueueError::MessageTooLarge`.
+    pub fn produce(&mut self, bytes: &[u8]) -> Result<usize, QueueError> {
+        let q = self.queue;
+        let size = record_size(bytes.len());
+        if size > q.capacity() {
+            return Err(QueueError::MessageTooLarge);
+        }
+        let pos = q.header.tail.load(Ordering::Relaxed);
+        let seq = q.header.count.load(Ordering::Relaxed);
+        q.header.tail_intent.store(pos + size, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let mut header = [0u8; RECORD_HEADER];
+        header[..8].copy_from_slice(&(bytes.len() as u64).to_le_bytes());
+        header[8..].copy_from_slice(&(seq as u64).to_le_bytes());
+        q.copy_in(pos, &header);
+        q.copy_in(pos + RECORD_HEADER, bytes);
+
+        q.header.tail.store(pos + size, Ordering::Release);
+        q.header.count.store(seq + 1, Ordering::Relaxed);
+        Ok(seq)
+    }
+}
+
+pub struct VarConsumer<'a> {
+    queue: &'a VarQueue,
+    pos:   usize,
+    // sequence of the next record, unknown until the first read after creation or a resync
+    seq:   Option<usize>,
+    buf:   Vec<u8>,
+}
+
+impl<'a> From<&'a VarQueue> for VarConsumer<'a> {
+    /// Starts at the next record to be produced
+    fn from(queue: &'a VarQueue) -> Self {
+        Self {
+            queue,
+            pos: queue.header.tail.load(Ordering::Acquire),
+            seq: None,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<'a> VarConsumer<'a> {
+    // whether the producer started overwriting anything at or after our position
+    fn overwritten(&self) -> bool {
+        self.queue.header.tail_intent.load(Ordering::Relaxed) > self.pos + self.queue.capacity()
+    }
+
+    /// Copies out the next record. Stays `SpedPast` until `resync` is called.
+    pub fn try_consume(&mut self) -> Result<&[u8], ReadError> {
+        let q = self.queue;
+        let tail = q.header.tail.load(Ordering::Acquire);
+        if tail == self.pos {
+            return Err(ReadError::Empty);
+        }
+        if tail - self.pos > q.capacity() {
+            return Err(ReadError::SpedPast);
+        }
+
+        let mut header = [0u8; RECORD_HEADER];
+        q.copy_out(self.pos, &mut header);
+        let len = u64::from_le_bytes(header[..8].try_into().unwrap()) as usize;
+        let seq = u64::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        // a torn header can hold any length, don't trust it before validating
+        if record_size(len) > q.capacity() {
+            return Err(ReadError::SpedPast);
+        }
+        self.buf.resize(len, 0);
+        q.copy_out(self.pos + RECORD_HEADER, &mut self.buf);
+
+        fence(Ordering::Acquire);
+        if self.overwritten() || self.seq.is_some_and(|s| s != seq) {
+            return Err(ReadError::SpedPast);
+        }
+        self.pos += record_size(len);
+        self.seq = Some(seq + 1);
+        Ok(&self.buf)
+    }
+
+    /// Continues with the next record to be produced
+    pub fn resync(&mut self) {
+        self.pos = self.queue.header.tail.load(Ordering::Acquire);
+        self.seq = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_sizes() {
+        let q = VarQueue::new(256).unwrap();
+        let mut p = VarProducer::from(q);
+        let mut c = VarConsumer::from(q);
+        assert_eq!(c.try_consume(), Err(ReadError::Empty));
+        assert!(matches!(p.produce(&[0; 256]), Err(QueueError::MessageTooLarge)));
+
+        // records of every length, wrapping around the buffer a couple of times
+        for len in 0..100 {
+            let msg: Vec<u8> = (0..len as u8).collect();
+            assert_eq!(p.produce(&msg).unwrap(), len);
+            assert_eq!(c.try_consume().unwrap(), &msg[..]);
+        }
+        assert_eq!(c.try_consume(), Err(ReadError::Empty));
+        assert_eq!(q.count(), 100);
+
+        // a record that spans the whole buffer
+        p.produce(&[7; 240]).unwrap();
+        assert_eq!(c.try_consume().unwrap(), &[7; 240]);
+    }
+
+    #[test]
+    fn attach() {
+        assert!(matches!(VarQueue::new(8), Err(QueueError::SizeTooSmall)));
+        let mut header = VarQueueHeader {
+            is_initialized: 0,
+            mask:           255,
+            tail_intent:    AtomicUsize::new(0),
+            tail:           AtomicUsize::new(0),
+            count:          AtomicUsize::new(0),
+        };
+        assert!(matches!(unsafe { VarQueue::from_initialized_ptr(&mut header) }, Err(QueueError::UnInitialized)));
+
+        let q = VarQueue::new(256).unwrap();
+        let attached = unsafe { VarQueue::from_initialized_ptr(&q.header as *const _ as *mut VarQueueHeader) }.unwrap();
+        assert_eq!(attached.capacity(), 256);
+        let mut p = VarProducer::from(q);
+        let mut c = VarConsumer::from(attached);
+        p.produce(&[1, 2, 3]).unwrap();
+        assert_eq!(c.try_consume().unwrap(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn sped_past() {
+        let q = VarQueue::new(128).unwrap();
+        let mut p = VarProducer::from(q);
+        let mut c = VarConsumer::from(q);
+        // 56 byte records
+        p.produce(&[1; 40]).unwrap();
+        p.produce(&[2; 40]).unwrap();
+        assert_eq!(c.try_consume().unwrap(), &[1; 40]);
+        // wraps around and overwrites the first record only
+        p.produce(&[3; 40]).unwrap();
+        assert_eq!(c.try_consume().unwrap(), &[2; 40]);
+        // the fifth record overwrites the start of the third
+        p.produce(&[4; 40]).unwrap();
+        p.produce(&[5; 40]).unwrap();
+        assert_eq!(c.try_consume(), Err(ReadError::SpedPast));
+        assert_eq!(c.try_consume(), Err(ReadError::SpedPast));
+        c.resync();
+        assert_eq!(c.try_consume(), Err(ReadError::Empty));
+        p.produce(&[6; 10]).unwrap();
+        assert_eq!(c.try_consume().unwrap(), &[6; 10]);
+    }
+
+    #[test]
+    fn var_multithread() {
+        const N: usize = 200000;
+        let q = VarQueue::new(4096).unwrap();
+        let mut c = VarConsumer::from(q);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut p = VarProducer::from(q);
+                let mut msg = Vec::new();
+                for i in 0..N {
+                    // the sequence number followed by a length and bytes derived from it
+                    msg.clear();
+                    msg.extend_from_slice(&i.to_le_bytes());
+                    msg.resize(8 + i % 200, i as u8);
+                    assert_eq!(p.produce(&msg).unwrap(), i);
+                    if i % 64 == 0 {
+                        std::thread::yield_now();
+                    }
+                }
+                done.store(true, Ordering::Release);
+            });
+            let mut n_read = 0;
+            let mut last = None;
+            loop {
+                match c.try_consume() {
+                    Ok(msg) => {
+                        let i = usize::from_le_bytes(msg[..8].try_into().unwrap());
+                        assert_eq!(msg.len(), 8 + i % 200);
+                        assert!(msg[8..].iter().all(|&b| b == i as u8));
+                        assert!(last.is_none_or(|l| i > l));
+                        last = Some(i);
+                        n_read += 1;
+                    }
+                    Err(ReadError::Empty) if done.load(Ordering::Acquire) => break,
+                    Err(ReadError::Empty) => std::thread::yield_now(),
+                    Err(ReadError::SpedPast) => c.resync(),
+                }
+            }
+            assert!(n_read > 0);
+        });
+    }
+}
@@ -1,20 +1,69 @@
-use std::{alloc::Layout, mem::MaybeUninit, ops::Index};
+use std::{alloc::Layout, cell::UnsafeCell, marker::PhantomData, mem::{align_of, size_of, MaybeUninit}, ops::Index, sync::atomic::{compiler_fence, AtomicUsize, Ordering}};
 use crate::seqlock::*;
+use crate::wait::WaitStrategy;
 
 #[derive(Debug)]
 #[repr(C)]
 pub struct VectorHeader {
     elsize: usize,
-    bufsize: usize
+    bufsize: usize,
+    // number of dirty blocks after the buffer, 0 if writes aren't tracked
+    dirty_blocks: usize,
+    // seqlock version of multi slot transactions
+    version: TransactionVersion,
 }
 
+// On its own cache line, every read and write loads the fields before it.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct TransactionVersion(AtomicUsize);
+
+/// Slots per dirty block, see `SeqlockVector::new_tracked`
+pub const DIRTY_BLOCK_LEN: usize = 64;
+
+// Bumped on every write to one of its slots. On its own cache line so writers of different blocks don't contend.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct DirtyBlock(AtomicUsize);
+
+/// `S` is the type of the slots, see `Slot`
 #[repr(C, align(64))]
-pub struct SeqlockVector<T> {
+pub struct SeqlockVector<T, S = Seqlock<T>> {
     header: VectorHeader,
-    buffer: [Seqlock<T>],
+    _t:     PhantomData<T>,
+    buffer: [S],
 }
 impl<T: Copy> SeqlockVector<T> {
     pub fn new(len: usize) -> &'static Self {
+        Self::with_slots(len)
+    }
+
+    /// Keeps a counter per `DIRTY_BLOCK_LEN` slots that every write bumps, so `changed_since`
+    /// only has to look at the slots of blocks that were written to
+    pub fn new_tracked(len: usize) -> &'static Self {
+        unsafe {
+            let ptr = std::alloc::alloc_zeroed(
+                Layout::array::<u8>(Self::size_of_tracked(len))
+                    .unwrap()
+                    .align_to(64)
+                    .unwrap()
+                    .pad_to_align(),
+            );
+            Self::from_uninitialized_ptr_tracked(ptr, len)
+        }
+    }
+
+    /// See `Seqlock::write_multi`
+    pub fn write_multi(&self, pos: usize, item: &T) {
+        self.pos_assert(pos);
+        self.load(pos).write_multi(item);
+        self.mark_dirty(pos);
+    }
+}
+
+impl<T: Copy, S: Slot<T>> SeqlockVector<T, S> {
+    /// Like `new` with slots of type `S`, e.g. `SmallSeqlock`
+    pub fn with_slots(len: usize) -> &'static Self {
         // because we don't need len to be power of 2
         let size = Self::size_of(len);
         unsafe {
@@ -31,29 +80,67 @@ impl<T: Copy> SeqlockVector<T> {
 
     pub const fn size_of(len: usize) -> usize {
         std::mem::size_of::<VectorHeader>()
-            + len * std::mem::size_of::<Seqlock<T>>()
+            + len * std::mem::size_of::<S>()
     }
 
     pub fn from_uninitialized_ptr(
         ptr: *mut u8,
         len: usize,
     ) -> &'static Self {
+        Self::init(ptr, len, 0)
+    }
+
+    /// Buffer padded to a cache line, followed by the dirty blocks
+    pub const fn size_of_tracked(len: usize) -> usize {
+        (size_of::<VectorHeader>().next_multiple_of(align_of::<S>()) + len * size_of::<S>()).next_multiple_of(64)
+            + len.div_ceil(DIRTY_BLOCK_LEN) * size_of::<DirtyBlock>()
+    }
+
+    /// `ptr` has to point to `size_of_tracked(len)` bytes
+    pub fn from_uninitialized_ptr_tracked(ptr: *mut u8, len: usize) -> &'static Self {
+        Self::init(ptr, len, len.div_ceil(DIRTY_BLOCK_LEN))
+    }
+
+    fn init(ptr: *mut u8, len: usize, dirty_blocks: usize) -> &'static Self {
         unsafe {
             // why len? because the size in the fat pointer ONLY cares about the unsized part of the struct
             // i.e. the length of the buffer
-            let q = &mut *(std::ptr::slice_from_raw_parts_mut(ptr, len) as *mut SeqlockVector<T>);
-            let elsize = std::mem::size_of::<Seqlock<T>>();
+            let q = &mut *(std::ptr::slice_from_raw_parts_mut(ptr, len) as *mut SeqlockVector<T, S>);
+            let elsize = std::mem::size_of::<S>();
             q.header.bufsize = len;
             q.header.elsize = elsize;
+            q.header.version = TransactionVersion::default();
+            q.header.dirty_blocks = dirty_blocks;
+            for b in 0..dirty_blocks {
+                q.dirty_block(b).0.store(0, Ordering::Relaxed);
+            }
             q
         }
     }
 
+    fn dirty_block(&self, block: usize) -> &DirtyBlock {
+        let end = self.buffer.as_ptr_range().end as *const u8;
+        unsafe {
+            let start = end.add(end.align_offset(64)) as *const DirtyBlock;
+            &*start.add(block)
+        }
+    }
+
+    fn mark_dirty(&self, pos: usize) {
+        if self.header.dirty_blocks != 0 {
+            self.dirty_block(pos / DIRTY_BLOCK_LEN).0.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    pub fn is_tracked(&self) -> bool {
+        self.header.dirty_blocks != 0
+    }
+
     #[allow(dead_code)]
     fn from_initialized_ptr(ptr: *mut VectorHeader) -> &'static Self {
         unsafe {
             let len = (*ptr).bufsize;
-            &*(std::ptr::slice_from_raw_parts_mut(ptr, len) as *const SeqlockVector<T>)
+            &*(std::ptr::slice_from_raw_parts_mut(ptr, len) as *const SeqlockVector<T, S>)
         }
     }
 
@@ -61,7 +148,7 @@ impl<T: Copy> SeqlockVector<T> {
         self.header.bufsize
     }
 
-    fn load(&self, pos: usize) -> &Seqlock<T> {
+    fn load(&self, pos: usize) -> &S {
         unsafe { self.buffer.get_unchecked(pos) }
     }
 
@@ -72,6 +159,7 @@ impl<T: Copy> SeqlockVector<T> {
     pub fn write_unchecked(&self, pos: usize, item: &T) {
         let lock = self.load(pos);
         lock.write(item);
+        self.mark_dirty(pos);
     }
 
     pub fn write(&self, pos: usize, item: &T) {
@@ -90,21 +178,167 @@ impl<T: Copy> SeqlockVector<T> {
     }
 
     pub fn read_copy_unchecked(&self, pos:usize) -> T {
-        let mut out = unsafe {MaybeUninit::uninit().assume_init()};
-        let lock = self.load(pos);
-        lock.read(&mut out);
-        out
+        let mut out = MaybeUninit::<T>::uninit();
+        // T: Copy so writing into the uninitialized memory never drops anything
+        self.load(pos).read(unsafe { &mut *out.as_mut_ptr() });
+        unsafe { out.assume_init() }
     }
     pub fn read_copy(&self, pos: usize) -> T {
         self.pos_assert(pos);
         self.read_copy_unchecked(pos)
     }
 
-    pub fn iter(&self) -> VectorIterator<'_, T> {
+    /// See `Seqlock::read_if_changed`
+    pub fn read_if_changed(&self, pos: usize, result: &mut T, last_version: &mut usize) -> bool {
+        self.pos_assert(pos);
+        self.load(pos).read_if_changed(result, last_version)
+    }
+
+    /// Polls slot `pos` with `wait` until its version moves on from `last_version`
+    pub fn wait_for_change(
+        &self,
+        pos: usize,
+        result: &mut T,
+        last_version: &mut usize,
+        wait: &impl WaitStrategy,
+    ) {
+        self.pos_assert(pos);
+        let lock = self.load(pos);
+        let mut n = 0;
+        while !lock.read_if_changed(result, last_version) {
+            wait.wait(n);
+            n += 1;
+        }
+    }
+
+    /// Applies all writes of `f` as one: `read_consistent` sees either none or all of them.
+    /// Only one transaction may run at a time. Plain `read`s still see every slot on its own.
+    pub fn transaction<R>(&self, f: impl FnOnce(&mut Transaction<'_, T, S>) -> R) -> R {
+        let version = &self.header.version.0;
+        let v = version.fetch_add(1, Ordering::Release);
+        compiler_fence(Ordering::AcqRel);
+        // ends the transaction even if `f` panics, so readers don't wait for it forever
+        let _end = TransactionEnd { version, v };
+        f(&mut Transaction { vector: self })
+    }
+
+    // a consistent read of slot `pos` and the version it was at, None if it was being written
+    fn read_versioned(&self, pos: usize) -> Option<(T, usize)> {
+        let lock = self.load(pos);
+        let ver = lock.version();
+        let mut out = MaybeUninit::<T>::uninit();
+        if ver & 1 == 1 || lock.read_with_version(unsafe { &mut *out.as_mut_ptr() }, ver).is_err() {
+            return None;
+        }
+        Some((unsafe { out.assume_init() }, ver))
+    }
+
+    // Reads `positions` once, handing them to `visit`, and then checks that no slot or transaction
+    // version moved in the meantime. False means the reads have to be redone.
+    fn collect(&self, positions: impl Iterator<Item = usize> + Clone, mut visit: impl FnMut(T)) -> bool {
+        let v = self.header.version.0.load(Ordering::Acquire);
+        if v & 1 == 1 {
+            return false;
+        }
+        // versions only grow, so an unchanged sum means no slot was written in between
+        let mut sum = 0usize;
+        for pos in positions.clone() {
+            let Some((t, ver)) = self.read_versioned(pos) else {
+                return false;
+            };
+            sum = sum.wrapping_add(ver);
+            visit(t);
+        }
+        let check = positions.fold(0usize, |sum, pos| sum.wrapping_add(self.load(pos).version()));
+        check == sum && self.header.version.0.load(Ordering::Acquire) == v
+    }
+
+    /// Reads slot `positions[i]` into `out[i]`, all as of the same moment and never from a half applied transaction
+    pub fn read_consistent(&self, positions: &[usize], out: &mut [T]) {
+        assert_eq!(positions.len(), out.len(), "One output per position");
+        for &pos in positions {
+            self.pos_assert(pos);
+        }
+        loop {
+            let mut i = 0;
+            let done = self.collect(positions.iter().copied(), |t| {
+                out[i] = t;
+                i += 1;
+            });
+            if done {
+                return;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Copies all slots as of the same moment, see `read_consistent`. Starts over whenever a slot
+    /// is written during the copy, so with a busy writer `snapshot_relaxed` may be the better choice.
+    pub fn snapshot(&self, out: &mut Vec<T>) {
+        loop {
+            out.clear();
+            if self.collect(0..self.len(), |t| out.push(t)) {
+                return;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Copies every slot consistently on its own, recording the version it was read at in `versions`.
+    /// Returns how many slots were written during the copy, `slot_version(i) != versions[i]` tells which.
+    pub fn snapshot_relaxed(&self, out: &mut Vec<T>, versions: &mut Vec<usize>) -> usize {
+        out.clear();
+        versions.clear();
+        for pos in 0..self.len() {
+            let (t, ver) = loop {
+                if let Some(r) = self.read_versioned(pos) {
+                    break r;
+                }
+                std::hint::spin_loop();
+            };
+            out.push(t);
+            versions.push(ver);
+        }
+        versions.iter().enumerate().filter(|&(pos, &v)| self.load(pos).version() != v).count()
+    }
+
+    /// Slots written since the last time `cursor` was passed in, with their current value.
+    /// A fresh cursor yields every slot that was ever written. Without tracking all slots are checked.
+    pub fn changed_since<'a>(&'a self, cursor: &'a mut Cursor) -> ChangedSlots<'a, T, S> {
+        cursor.blocks.resize(self.len().div_ceil(DIRTY_BLOCK_LEN), 0);
+        cursor.versions.resize(self.len(), 0);
+        ChangedSlots {
+            vector: self,
+            cursor,
+            block: 0,
+            pos: 0,
+            end: 0,
+            pending: None,
+        }
+    }
+
+    pub fn slot_version(&self, pos: usize) -> usize {
+        self.pos_assert(pos);
+        self.load(pos).version()
+    }
+
+    pub fn iter(&self) -> VectorIterator<'_, T, S> {
         VectorIterator{vector: self, next_id: 0}
     }
 }
 
+impl<T: Copy + PartialEq> SeqlockVector<T> {
+    /// See `Seqlock::write_if_changed`
+    pub fn write_if_changed(&self, pos: usize, item: &T) -> bool {
+        self.pos_assert(pos);
+        let changed = self.load(pos).write_if_changed(item);
+        if changed {
+            self.mark_dirty(pos);
+        }
+        changed
+    }
+}
+
 #[cfg(feature = "shmem")]
 impl<T: Copy> SeqlockVector<T> {
     pub fn shared<P: AsRef<std::path::Path>>(
@@ -140,18 +374,197 @@ impl<T: Copy> SeqlockVector<T> {
         }
     }
 }
-impl<T: Clone + std::fmt::Debug> std::fmt::Debug for SeqlockVector<T> {
+impl<T: Clone + std::fmt::Debug, S> std::fmt::Debug for SeqlockVector<T, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "SeqlockVector:\nHeader:\n{:?}", self.header)
     }
 }
 
-pub struct VectorIterator<'a, T> {
-    vector: &'a SeqlockVector<T>,
+/// `SeqlockVector` of byte buffers whose size is picked at runtime, e.g. from a schema.
+/// `VectorHeader.elsize` holds the stride of a slot: its version plus the payload, padded to a cache line.
+#[repr(C, align(64))]
+pub struct DynSeqlockVector {
+    header: VectorHeader,
+    buffer: [CacheLine],
+}
+
+#[repr(C, align(64))]
+struct CacheLine(UnsafeCell<[u8; 64]>);
+unsafe impl Send for DynSeqlockVector {}
+unsafe impl Sync for DynSeqlockVector {}
+
+impl DynSeqlockVector {
+    /// `len` slots of at least `payload` bytes each
+    pub fn new(len: usize, payload: usize) -> &'static Self {
+        let size = Self::size_of(len, payload);
+        unsafe {
+            let ptr = std::alloc::alloc_zeroed(
+                Layout::array::<u8>(size)
+                    .unwrap()
+                    .align_to(64)
+                    .unwrap()
+                    .pad_to_align(),
+            );
+            Self::from_uninitialized_ptr(ptr, len, payload)
+        }
+    }
+
+    const fn stride(payload: usize) -> usize {
+        (size_of::<AtomicUsize>() + payload).next_multiple_of(64)
+    }
+
+    pub const fn size_of(len: usize, payload: usize) -> usize {
+        size_of::<VectorHeader>() + len * Self::stride(payload)
+    }
+
+    pub fn from_uninitialized_ptr(ptr: *mut u8, len: usize, payload: usize) -> &'static Self {
+        let elsize = Self::stride(payload);
+        unsafe {
+            let lines = len * elsize / size_of::<CacheLine>();
+            let v = &mut *(std::ptr::slice_from_raw_parts_mut(ptr, lines) as *mut DynSeqlockVector);
+            v.header.bufsize = len;
+            v.header.elsize = elsize;
+            v.header.version = TransactionVersion::default();
+            v.header.dirty_blocks = 0;
+            v
+        }
+    }
+
+    #[allow(dead_code)]
+    fn from_initialized_ptr(ptr: *mut VectorHeader) -> &'static Self {
+        unsafe {
+            let lines = (*ptr).bufsize * (*ptr).elsize / size_of::<CacheLine>();
+            &*(std::ptr::slice_from_raw_parts_mut(ptr, lines) as *const DynSeqlockVector)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.bufsize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes of payload per slot, can be more than asked for because slots fill whole cache lines
+    pub fn payload(&self) -> usize {
+        self.header.elsize - size_of::<AtomicUsize>()
+    }
+
+    pub fn get(&self, pos: usize) -> &SeqlockSlice<u8> {
+        assert!(pos < self.len(), "OutOfBounds: index {pos} larger than size {}", self.header.bufsize);
+        unsafe {
+            let slot = (self.buffer.as_ptr() as *const u8).add(pos * self.header.elsize);
+            &*(std::ptr::slice_from_raw_parts(slot, self.payload()) as *const SeqlockSlice<u8>)
+        }
+    }
+
+    /// Reads the first `result.len()` bytes of slot `pos`
+    pub fn read(&self, pos: usize, result: &mut [u8]) {
+        self.get(pos).read_into(result)
+    }
+
+    /// Writes `item` to the first `item.len()` bytes of slot `pos`
+    pub fn write(&self, pos: usize, item: &[u8]) {
+        self.get(pos).write_from(item)
+    }
+}
+
+impl std::fmt::Debug for DynSeqlockVector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DynSeqlockVector:\nHeader:\n{:?}", self.header)
+    }
+}
+
+struct TransactionEnd<'a> {
+    version: &'a AtomicUsize,
+    v:       usize,
+}
+
+impl Drop for TransactionEnd<'_> {
+    fn drop(&mut self) {
+        compiler_fence(Ordering::AcqRel);
+        self.version.store(self.v.wrapping_add(2), Ordering::Release);
+    }
+}
+
+pub struct Transaction<'a, T, S = Seqlock<T>> {
+    vector: &'a SeqlockVector<T, S>,
+}
+
+impl<T: Copy, S: Slot<T>> Transaction<'_, T, S> {
+    pub fn write(&mut self, pos: usize, item: &T) {
+        self.vector.write(pos, item)
+    }
+}
+
+/// What a reader saw during its last `SeqlockVector::changed_since`
+#[derive(Debug, Default, Clone)]
+pub struct Cursor {
+    blocks:   Vec<usize>,
+    versions: Vec<usize>,
+}
+
+pub struct ChangedSlots<'a, T, S = Seqlock<T>> {
+    vector:  &'a SeqlockVector<T, S>,
+    cursor:  &'a mut Cursor,
+    block:   usize,
+    // slots of the current block left to check
+    pos:     usize,
+    end:     usize,
+    // block and dirty count to store in the cursor once all of the block's slots were checked
+    pending: Option<(usize, usize)>,
+}
+
+impl<T: Copy, S: Slot<T>> Iterator for ChangedSlots<'_, T, S> {
+    type Item = (usize, T);
+
+    fn next(&mut self) -> Option<(usize, T)> {
+        let v = self.vector;
+        loop {
+            while self.pos < self.end {
+                let pos = self.pos;
+                self.pos += 1;
+                let (t, ver) = loop {
+                    if let Some(r) = v.read_versioned(pos) {
+                        break r;
+                    }
+                    std::hint::spin_loop();
+                };
+                if ver != self.cursor.versions[pos] {
+                    self.cursor.versions[pos] = ver;
+                    return Some((pos, t));
+                }
+            }
+            // an iterator dropped halfway through a block leaves it to be checked again next time
+            if let Some((b, count)) = self.pending.take() {
+                self.cursor.blocks[b] = count;
+            }
+            if self.block == self.cursor.blocks.len() {
+                return None;
+            }
+            let b = self.block;
+            self.block += 1;
+            // recorded before reading the slots, a write in the meantime shows up next time
+            if v.is_tracked() {
+                let count = v.dirty_block(b).0.load(Ordering::Acquire);
+                if count == self.cursor.blocks[b] {
+                    continue;
+                }
+                self.pending = Some((b, count));
+            }
+            self.pos = b * DIRTY_BLOCK_LEN;
+            self.end = v.len().min(self.pos + DIRTY_BLOCK_LEN);
+        }
+    }
+}
+
+pub struct VectorIterator<'a, T, S = Seqlock<T>> {
+    vector: &'a SeqlockVector<T, S>,
     next_id: usize
 }
 
-impl<'a, T: Copy + Clone> Iterator for VectorIterator<'a, T>
+impl<'a, T: Copy + Clone, S: Slot<T>> Iterator for VectorIterator<'a, T, S>
 {
     type Item = T;
 
@@ -165,3 +578,165 @@ impl<'a, T: Copy + Clone> Iterator for VectorIterator<'a, T>
         }
 
 }}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_if_changed() {
+        let v = SeqlockVector::<u64>::new(4);
+        assert!(!v.write_if_changed(1, &0));
+        assert!(v.write_if_changed(1, &5));
+        assert!(!v.write_if_changed(1, &5));
+        let mut m = 0;
+        let mut version = 0;
+        assert!(v.read_if_changed(1, &mut m, &mut version));
+        assert_eq!((m, version), (5, 2));
+    }
+
+    #[test]
+    fn transaction() {
+        let v = SeqlockVector::<usize>::new(8);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut out = [0; 3];
+                while !done.load(Ordering::Relaxed) {
+                    v.read_consistent(&[6, 1, 3], &mut out);
+                    assert_eq!(out[1], out[2]);
+                    assert!(out[0] == out[1] || out[0] == out[1] + 1);
+                }
+            });
+            s.spawn(|| {
+                for i in 1..100000 {
+                    v.transaction(|tx| {
+                        tx.write(1, &i);
+                        tx.write(3, &i);
+                    });
+                    // on its own, so it can be ahead of the other two
+                    v.write(6, &i);
+                    if i % 64 == 0 {
+                        std::thread::yield_now();
+                    }
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+        });
+        let mut out = [0; 2];
+        v.read_consistent(&[3, 1], &mut out);
+        assert_eq!(out, [99999; 2]);
+        v.transaction(|tx| tx.write(0, &1));
+        assert_eq!(v.header.version.0.load(Ordering::Relaxed), 2 * 100000);
+
+        // a panicking transaction still ends
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.transaction(|tx| {
+                tx.write(1, &0);
+                panic!("aborted");
+            })
+        }));
+        assert!(r.is_err());
+        assert_eq!(v.header.version.0.load(Ordering::Relaxed), 2 * 100001);
+        v.read_consistent(&[3, 1], &mut out);
+        assert_eq!(out, [99999, 0]);
+        assert_eq!(std::mem::offset_of!(VectorHeader, version), 64);
+    }
+
+    #[test]
+    fn snapshot() {
+        let v = SeqlockVector::<usize>::new(64);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut out = Vec::new();
+                let mut versions = Vec::new();
+                while !done.load(Ordering::Relaxed) {
+                    v.snapshot(&mut out);
+                    assert_eq!(out.len(), 64);
+                    assert!(out.iter().all(|&i| i == out[0]));
+                    // every slot on its own is still whole
+                    v.snapshot_relaxed(&mut out, &mut versions);
+                    for (i, &ver) in versions.iter().enumerate() {
+                        assert_eq!(ver, 2 * out[i]);
+                    }
+                }
+            });
+            s.spawn(|| {
+                for i in 1..20000 {
+                    v.transaction(|tx| {
+                        for pos in 0..64 {
+                            tx.write(pos, &i);
+                        }
+                    });
+                    if i % 16 == 0 {
+                        std::thread::yield_now();
+                    }
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+        });
+        let mut out = Vec::new();
+        let mut versions = Vec::new();
+        v.write(5, &1);
+        assert_eq!(v.snapshot_relaxed(&mut out, &mut versions), 0);
+        assert_eq!((out[5], versions[5], v.slot_version(5)), (1, 2 * 20000, 2 * 20000));
+    }
+
+    #[test]
+    fn changed_since() {
+        let v = SeqlockVector::<usize>::new_tracked(200);
+        assert!(v.is_tracked());
+        assert_eq!(v.header.dirty_blocks, 4);
+        let mut cursor = Cursor::default();
+        assert_eq!(v.changed_since(&mut cursor).count(), 0);
+        v.write(199, &1);
+        v.write(3, &2);
+        v.write(70, &3);
+        assert_eq!(v.changed_since(&mut cursor).collect::<Vec<_>>(), [(3, 2), (70, 3), (199, 1)]);
+        assert_eq!(v.changed_since(&mut cursor).count(), 0);
+        v.write(70, &4);
+        v.write(70, &5);
+        assert!(!v.write_if_changed(70, &5));
+        assert!(v.write_if_changed(71, &6));
+        assert_eq!(v.changed_since(&mut cursor).collect::<Vec<_>>(), [(70, 5), (71, 6)]);
+        // only the blocks of 70 and 71 got written
+        assert_eq!(cursor.blocks, [1, 4, 0, 1]);
+        // another reader starts from scratch
+        assert_eq!(v.changed_since(&mut Cursor::default()).count(), 4);
+
+        // stopping halfway through a block leaves its other changes for the next scan
+        v.write(5, &7);
+        v.write(6, &8);
+        assert_eq!(v.changed_since(&mut cursor).next(), Some((5, 7)));
+        assert_eq!(v.changed_since(&mut cursor).collect::<Vec<_>>(), [(6, 8)]);
+        assert_eq!(v.changed_since(&mut cursor).count(), 0);
+
+        // without tracking every slot gets checked
+        let v = SeqlockVector::<usize>::new(100);
+        let mut cursor = Cursor::default();
+        v.write(99, &1);
+        assert_eq!(v.changed_since(&mut cursor).collect::<Vec<_>>(), [(99, 1)]);
+        assert_eq!(v.changed_since(&mut cursor).count(), 0);
+    }
+
+    #[test]
+    fn dyn_vector() {
+        let v = DynSeqlockVector::new(8, 100);
+        assert_eq!(v.len(), 8);
+        assert_eq!(v.header.elsize, 128);
+        assert_eq!(v.payload(), 120);
+        for i in 0..8 {
+            v.write(i, &[i as u8; 100]);
+        }
+        let mut out = [0u8; 100];
+        for i in 0..8 {
+            v.read(i, &mut out);
+            assert_eq!(out, [i as u8; 100]);
+        }
+        assert_eq!(v.get(3).version(), 2);
+        let v2 = DynSeqlockVector::from_initialized_ptr(v as *const _ as *mut VectorHeader);
+        v2.read(7, &mut out);
+        assert_eq!(out, [7; 100]);
+    }
+}
@@ -0,0 +1,77 @@
+use std::{hint::spin_loop, time::Duration};
+
+/// Decides what a poller does between two unsuccessful polls.
+/// `n` is the number of consecutive unsuccessful polls so far, starting at 0.
+pub trait WaitStrategy {
+    fn wait(&self, n: usize);
+}
+
+/// Hammers the cache line, lowest latency and a burnt core
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BusySpin;
+
+impl WaitStrategy for BusySpin {
+    #[inline]
+    fn wait(&self, _n: usize) {}
+}
+
+/// Spins with `_mm_pause` (through `spin_loop`), which frees up resources for the hyperthread sibling
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pause;
+
+impl WaitStrategy for Pause {
+    #[inline]
+    fn wait(&self, _n: usize) {
+        spin_loop()
+    }
+}
+
+/// Spins `2^n` `_mm_pause` instructions, capping `n` at `max_shift`
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub max_shift: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { max_shift: 10 }
+    }
+}
+
+impl WaitStrategy for Backoff {
+    #[inline]
+    fn wait(&self, n: usize) {
+        let shift = (n as u32).min(self.max_shift);
+        for _ in 0..1usize << shift {
+            spin_loop()
+        }
+    }
+}
+
+/// Gives up the timeslice with `std::thread::yield_now`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Yield;
+
+impl WaitStrategy for Yield {
+    #[inline]
+    fn wait(&self, _n: usize) {
+        std::thread::yield_now()
+    }
+}
+
+/// Sleeps for a fixed duration between polls
+#[derive(Debug, Clone, Copy)]
+pub struct Sleep(pub Duration);
+
+impl Default for Sleep {
+    fn default() -> Self {
+        Self(Duration::from_micros(50))
+    }
+}
+
+impl WaitStrategy for Sleep {
+    #[inline]
+    fn wait(&self, _n: usize) {
+        std::thread::sleep(self.0)
+    }
+}